@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE suppressions (
+    suppression_id uuid NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('email', 'domain')),
+    value TEXT NOT NULL,
+    reason TEXT NOT NULL CHECK (reason IN ('manual', 'bounce', 'complaint', 'unsubscribe')),
+    note TEXT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (suppression_id),
+    UNIQUE (kind, value)
+);
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE email_layouts (
+    layout_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    html_template TEXT NOT NULL,
+    text_template TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (layout_id)
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN layout_id uuid NULL REFERENCES email_layouts (layout_id) ON DELETE SET NULL;
@@ -0,0 +1,6 @@
+-- Add migration script here
+CREATE TABLE worker_heartbeats (
+    worker_id uuid NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    PRIMARY KEY (worker_id)
+);
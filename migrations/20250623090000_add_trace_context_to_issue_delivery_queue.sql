@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN traceparent TEXT NULL,
+    ADD COLUMN tracestate TEXT NULL;
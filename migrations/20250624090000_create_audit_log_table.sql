@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE audit_log (
+    audit_log_id uuid NOT NULL,
+    user_id uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (audit_log_id)
+);
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at DESC);
@@ -0,0 +1,2 @@
+-- Add migration script here
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Keys are scoped to a user or, on anonymous routes, to a client.
+ALTER TABLE idempotency ADD COLUMN scope TEXT NULL;
+UPDATE idempotency SET scope = 'user:' || user_id;
+ALTER TABLE idempotency ALTER COLUMN scope SET NOT NULL;
+ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+ALTER TABLE idempotency DROP COLUMN user_id;
+ALTER TABLE idempotency ADD PRIMARY KEY (scope, idempotency_key);
+ALTER TABLE idempotency ADD COLUMN request_fingerprint BYTEA NULL;
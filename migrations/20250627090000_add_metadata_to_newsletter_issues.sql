@@ -0,0 +1,24 @@
+-- Add migration script here
+-- published_at was inserted as now() into a TEXT column, it parses back as a timestamp.
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz,
+    ALTER COLUMN published_at DROP NOT NULL;
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN created_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL,
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'draft'
+        CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled')),
+    -- NULL for issues published before it was recorded.
+    ADD COLUMN recipient_count INTEGER NULL,
+    ADD COLUMN completed_at timestamptz NULL;
+
+UPDATE newsletter_issues
+SET status = 'sending'
+WHERE EXISTS (
+    SELECT 1
+    FROM issue_delivery_queue q
+    WHERE q.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+);
+UPDATE newsletter_issues
+SET status = 'sent', completed_at = published_at
+WHERE status = 'draft';
@@ -0,0 +1,16 @@
+-- Add migration script here
+CREATE TABLE newsletter_deliveries (
+    delivery_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    -- NULL if the recipient could not be matched to a subscriber.
+    subscriber_id uuid NULL REFERENCES subscriptions (id) ON DELETE SET NULL,
+    subscriber_email TEXT NOT NULL,
+    outcome TEXT NOT NULL CHECK (outcome IN ('sent', 'failed', 'suppressed', 'skipped')),
+    provider_message_id TEXT NULL,
+    error TEXT NULL,
+    attempted_at timestamptz NOT NULL,
+    completed_at timestamptz NOT NULL,
+    PRIMARY KEY (delivery_id)
+);
+CREATE INDEX newsletter_deliveries_issue_idx ON newsletter_deliveries (newsletter_issue_id);
+CREATE INDEX newsletter_deliveries_subscriber_idx ON newsletter_deliveries (subscriber_id);
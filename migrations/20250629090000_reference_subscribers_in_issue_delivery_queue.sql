@@ -0,0 +1,26 @@
+-- Add migration script here
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN subscriber_id uuid NULL REFERENCES subscriptions (id) ON DELETE CASCADE;
+
+UPDATE issue_delivery_queue
+SET subscriber_id = subscriptions.id
+FROM subscriptions
+WHERE subscriptions.email = issue_delivery_queue.subscriber_email;
+-- Tasks whose subscriber no longer exists have nobody to deliver to.
+DELETE FROM issue_delivery_queue WHERE subscriber_id IS NULL;
+
+ALTER TABLE issue_delivery_queue
+    DROP CONSTRAINT issue_delivery_queue_pkey,
+    DROP COLUMN subscriber_email,
+    ALTER COLUMN subscriber_id SET NOT NULL,
+    ADD PRIMARY KEY (newsletter_issue_id, subscriber_id);
+
+UPDATE newsletter_issues
+SET status = 'sent', completed_at = now()
+WHERE
+    status = 'sending' AND
+    NOT EXISTS (
+        SELECT 1
+        FROM issue_delivery_queue q
+        WHERE q.newsletter_issue_id = newsletter_issues.newsletter_issue_id
+    );
@@ -0,0 +1,10 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    DROP CONSTRAINT newsletter_issues_status_check,
+    ADD CONSTRAINT newsletter_issues_status_check
+        CHECK (status IN ('draft', 'scheduled', 'sending', 'paused', 'sent', 'cancelled'));
+
+ALTER TABLE newsletter_deliveries
+    DROP CONSTRAINT newsletter_deliveries_outcome_check,
+    ADD CONSTRAINT newsletter_deliveries_outcome_check
+        CHECK (outcome IN ('sent', 'failed', 'suppressed', 'skipped', 'cancelled'));
@@ -0,0 +1,19 @@
+-- Add migration script here
+CREATE TABLE attachments (
+    attachment_id uuid NOT NULL,
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    content BYTEA NOT NULL,
+    -- Set for inline images, which the HTML body references as `cid:<content_id>`.
+    content_id TEXT NULL UNIQUE,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (attachment_id)
+);
+
+CREATE TABLE newsletter_issue_attachments (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    attachment_id uuid NOT NULL REFERENCES attachments (attachment_id),
+    PRIMARY KEY (newsletter_issue_id, attachment_id)
+);
+CREATE INDEX newsletter_issue_attachments_attachment_idx
+    ON newsletter_issue_attachments (attachment_id);
@@ -0,0 +1,12 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN archived BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+-- Same shape as the slugs of new issues: the title, then the start of the issue id.
+UPDATE newsletter_issues
+SET slug = concat_ws(
+    '-',
+    nullif(trim(BOTH '-' FROM left(regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g'), 60)), ''),
+    left(newsletter_issue_id::text, 8)
+);
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
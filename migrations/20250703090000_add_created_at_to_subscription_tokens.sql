@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Existing tokens count as issued now, so that pending subscribers can still confirm.
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE confirmation_email_outbox (
+    outbox_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    subscription_token TEXT NOT NULL,
+    n_attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at timestamptz NOT NULL,
+    last_error TEXT NULL,
+    traceparent TEXT NULL,
+    tracestate TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX confirmation_email_outbox_next_attempt_at_idx
+    ON confirmation_email_outbox (next_attempt_at);
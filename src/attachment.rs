@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::email_client::EmailAttachment;
+
+/// An uploaded file, without its content.
+pub struct Attachment {
+    pub attachment_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i32,
+    pub content_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List attachments", skip(pool))]
+pub async fn get_attachments(pool: &PgPool) -> Result<Vec<Attachment>, sqlx::Error> {
+    sqlx::query_as!(
+        Attachment,
+        r#"
+            SELECT
+                attachment_id,
+                file_name,
+                content_type,
+                length(content) AS "size!",
+                content_id,
+                created_at
+            FROM attachments
+            ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Inline images get a content id, derived from the attachment id.
+#[tracing::instrument(name = "Store attachment", skip(executor, content))]
+pub async fn insert_attachment(
+    executor: impl PgExecutor<'_>,
+    file_name: &str,
+    content_type: &str,
+    content: &[u8],
+    inline: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let attachment_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+            INSERT INTO attachments (
+                attachment_id,
+                file_name,
+                content_type,
+                content,
+                content_id,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        attachment_id,
+        file_name,
+        content_type,
+        content,
+        inline.then(|| attachment_id.to_string()),
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(attachment_id)
+}
+
+/// Returns `false` if there was no unused attachment with the given id:
+/// attachments sent with an issue are kept.
+#[tracing::instrument(name = "Delete attachment", skip(executor))]
+pub async fn delete_attachment(
+    executor: impl PgExecutor<'_>,
+    attachment_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+            DELETE FROM attachments
+            WHERE
+                attachment_id = $1 AND
+                NOT EXISTS (
+                    SELECT 1
+                    FROM newsletter_issue_attachments
+                    WHERE attachment_id = $1
+                )
+        "#,
+        attachment_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
+
+/// The combined size of the attachments with the given ids, `None` if any of them does not exist.
+#[tracing::instrument(name = "Measure attachments", skip(executor))]
+pub async fn total_size(
+    executor: impl PgExecutor<'_>,
+    attachment_ids: &[Uuid],
+) -> Result<Option<i64>, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+            SELECT count(*) AS "count!", coalesce(sum(length(content)), 0)::bigint AS "size!"
+            FROM attachments
+            WHERE attachment_id = ANY($1)
+        "#,
+        attachment_ids
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok((r.count == attachment_ids.len() as i64).then_some(r.size))
+}
+
+#[tracing::instrument(name = "Attach files to newsletter issue", skip(transaction))]
+pub async fn attach_to_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    attachment_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_issue_attachments (newsletter_issue_id, attachment_id)
+            SELECT $1, attachment_id
+            FROM unnest($2::uuid[]) AS attachment_id
+            ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        attachment_ids
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Load newsletter issue attachments", skip(executor))]
+pub async fn get_issue_attachments(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<EmailAttachment>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailAttachment,
+        r#"
+            SELECT a.file_name, a.content_type, a.content, a.content_id
+            FROM newsletter_issue_attachments ia
+            JOIN attachments a ON a.attachment_id = ia.attachment_id
+            WHERE ia.newsletter_issue_id = $1
+            ORDER BY a.created_at
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// E.g. `1.5 MB`.
+pub fn display_size(bytes: usize) -> String {
+    if bytes < 1_000_000 {
+        format!("{} KB", bytes.div_ceil(1_000))
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+    }
+}
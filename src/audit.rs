@@ -0,0 +1,206 @@
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpRequest, dev::Payload, http::header};
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::rate_limit::client_ip;
+
+/// An administrative action recorded in the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    NewsletterPublished,
+    NewsletterPaused,
+    NewsletterResumed,
+    NewsletterCancelled,
+    SuppressionAdded,
+    SuppressionRemoved,
+    LayoutCreated,
+    LayoutDeleted,
+    AttachmentUploaded,
+    AttachmentDeleted,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 14] = [
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::Logout,
+        AuditAction::PasswordChanged,
+        AuditAction::NewsletterPublished,
+        AuditAction::NewsletterPaused,
+        AuditAction::NewsletterResumed,
+        AuditAction::NewsletterCancelled,
+        AuditAction::SuppressionAdded,
+        AuditAction::SuppressionRemoved,
+        AuditAction::LayoutCreated,
+        AuditAction::LayoutDeleted,
+        AuditAction::AttachmentUploaded,
+        AuditAction::AttachmentDeleted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::NewsletterPublished => "newsletter_published",
+            AuditAction::NewsletterPaused => "newsletter_paused",
+            AuditAction::NewsletterResumed => "newsletter_resumed",
+            AuditAction::NewsletterCancelled => "newsletter_cancelled",
+            AuditAction::SuppressionAdded => "suppression_added",
+            AuditAction::SuppressionRemoved => "suppression_removed",
+            AuditAction::LayoutCreated => "layout_created",
+            AuditAction::LayoutDeleted => "layout_deleted",
+            AuditAction::AttachmentUploaded => "attachment_uploaded",
+            AuditAction::AttachmentDeleted => "attachment_deleted",
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{s} is not a valid audit action."))
+    }
+}
+
+/// Where an audited request came from.
+///
+/// The IP address is the one rate limiting sees, see [`client_ip`].
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for ClientInfo {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self {
+            ip_address: client_ip(req),
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(Into::into),
+        }))
+    }
+}
+
+pub struct AuditEntry {
+    pub audit_log_id: Uuid,
+    pub username: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct AuditFilter {
+    pub username: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Record an audit log entry", skip(executor, client))]
+pub async fn record(
+    executor: impl PgExecutor<'_>,
+    user_id: Option<Uuid>,
+    action: AuditAction,
+    target: Option<&str>,
+    client: &ClientInfo,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO audit_log (
+                audit_log_id,
+                user_id,
+                action,
+                target,
+                ip_address,
+                user_agent,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action.as_str(),
+        target,
+        client.ip_address,
+        client.user_agent,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Most recent entries first. `limit` is ignored when `None`.
+#[tracing::instrument(name = "List audit log entries", skip(pool, filter))]
+pub async fn get_entries(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    limit: Option<i64>,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEntry,
+        r#"
+            SELECT
+                a.audit_log_id,
+                u.username AS "username?",
+                a.action,
+                a.target,
+                a.ip_address,
+                a.user_agent,
+                a.created_at
+            FROM audit_log a
+            LEFT JOIN users u ON u.user_id = a.user_id
+            WHERE
+                ($1::text IS NULL OR u.username = $1) AND
+                ($2::text IS NULL OR a.action = $2) AND
+                ($3::timestamptz IS NULL OR a.created_at >= $3) AND
+                ($4::timestamptz IS NULL OR a.created_at < $4)
+            ORDER BY a.created_at DESC
+            LIMIT $5
+        "#,
+        filter.username,
+        filter.action.map(|a| a.as_str()),
+        filter.since,
+        filter.until,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+
+    #[test]
+    fn actions_round_trip_through_their_name() {
+        for action in AuditAction::ALL {
+            assert_eq!(
+                AuditAction::try_from(action.as_str().to_owned()),
+                Ok(action)
+            );
+        }
+        assert!(AuditAction::try_from("drop_tables".to_owned()).is_err());
+    }
+}
@@ -4,7 +4,7 @@ use argon2::{
     password_hash::{SaltString, rand_core::OsRng},
 };
 use secrecy::{ExposeSecret, SecretString};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool};
 
 use crate::telemetry::spawn_blocking_with_tracing;
 
@@ -89,11 +89,11 @@ fn verify_password_hash(
     Ok(())
 }
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, executor))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: SecretString,
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
 ) -> Result<(), anyhow::Error> {
     let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
         .await?
@@ -107,7 +107,7 @@ pub async fn change_password(
         password_hash.expose_secret(),
         user_id
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to change user's password in the database.")?;
 
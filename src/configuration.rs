@@ -1,10 +1,14 @@
 use config::{Config, File};
 use secrecy::{ExposeSecret, SecretString};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde::Deserialize;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::ConnectOptions;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
 use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
@@ -13,6 +17,26 @@ pub struct Settings {
     pub email_client: EmailClientSettings,
     pub hmac_secret: SecretString,
     pub redis_uri: SecretString,
+    #[serde(default)]
+    pub email_layout: EmailLayoutSettings,
+    #[serde(default)]
+    pub html_sanitizer: HtmlSanitizerSettings,
+    #[serde(default)]
+    pub readiness: ReadinessSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub bot_protection: BotProtectionSettings,
+    #[serde(default)]
+    pub idempotency: IdempotencySettings,
+    #[serde(default)]
+    pub attachments: AttachmentSettings,
+    #[serde(default)]
+    pub feed: FeedSettings,
+    #[serde(default)]
+    pub confirmation: ConfirmationSettings,
+    #[serde(default)]
+    pub metrics: MetricsSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -34,6 +58,303 @@ pub struct ApplicationSettings {
     pub base_url: String,
 }
 
+/// Branding applied to every outgoing email.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct EmailLayoutSettings {
+    /// Name of the admin-managed layout used when an issue does not pick one.
+    /// The built-in layout is used if it is unset or no such layout exists.
+    #[serde(default)]
+    pub default_layout: Option<String>,
+    /// Postal address printed in the footer, as required by anti-spam laws.
+    #[serde(default)]
+    pub physical_address: String,
+}
+
+/// Allow-list for the HTML body of newsletter issues, on top of a conservative
+/// set of formatting tags and attributes.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct HtmlSanitizerSettings {
+    /// E.g. `center`. `script` and `style` are always removed.
+    #[serde(default)]
+    pub extra_tags: Vec<String>,
+    /// Allowed on every tag, e.g. `style`.
+    #[serde(default)]
+    pub extra_attributes: Vec<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ReadinessSettings {
+    /// The instance is not ready if no delivery worker has reported in for that long.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_worker_heartbeat_age_seconds: u64,
+    /// How long each dependency check may take before it is reported as down.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub check_timeout_milliseconds: u64,
+}
+
+impl Default for ReadinessSettings {
+    fn default() -> Self {
+        Self {
+            max_worker_heartbeat_age_seconds: 60,
+            check_timeout_milliseconds: 2000,
+        }
+    }
+}
+
+impl ReadinessSettings {
+    pub fn max_worker_heartbeat_age(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.max_worker_heartbeat_age_seconds)
+    }
+
+    pub fn check_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.check_timeout_milliseconds)
+    }
+}
+
+/// Limits on the public subscription endpoints, which send emails to arbitrary
+/// addresses on behalf of anonymous callers, and on login attempts.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    /// Prefix of the Redis keys holding the buckets.
+    pub key_prefix: String,
+    /// Take the client IP from `Forwarded`/`X-Forwarded-For`. Only enable this
+    /// behind a proxy that overwrites those headers, callers can set them otherwise.
+    pub trust_forwarded_for: bool,
+    #[serde(deserialize_with = "TokenBucketSettings::deserialize_per_ip")]
+    pub per_ip: TokenBucketSettings,
+    #[serde(deserialize_with = "TokenBucketSettings::deserialize_per_email")]
+    pub per_email: TokenBucketSettings,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            key_prefix: "rate_limit".into(),
+            trust_forwarded_for: false,
+            per_ip: TokenBucketSettings::PER_IP,
+            per_email: TokenBucketSettings::PER_EMAIL,
+        }
+    }
+}
+
+/// A bucket holding up to `capacity` requests, refilled with one request every
+/// `refill_interval_milliseconds`. Fields left out take the defaults of the
+/// bucket being configured.
+#[derive(Clone, Copy)]
+pub struct TokenBucketSettings {
+    pub capacity: u32,
+    pub refill_interval_milliseconds: u64,
+}
+
+impl TokenBucketSettings {
+    pub const PER_IP: Self = Self {
+        capacity: 10,
+        refill_interval_milliseconds: 6_000,
+    };
+    pub const PER_EMAIL: Self = Self {
+        capacity: 3,
+        refill_interval_milliseconds: 1_200_000,
+    };
+
+    fn deserialize_per_ip<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        PartialTokenBucketSettings::deserialize(deserializer).map(|p| p.or(Self::PER_IP))
+    }
+
+    fn deserialize_per_email<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        PartialTokenBucketSettings::deserialize(deserializer).map(|p| p.or(Self::PER_EMAIL))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PartialTokenBucketSettings {
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    capacity: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    refill_interval_milliseconds: Option<u64>,
+}
+
+impl PartialTokenBucketSettings {
+    fn or(self, defaults: TokenBucketSettings) -> TokenBucketSettings {
+        TokenBucketSettings {
+            capacity: self.capacity.unwrap_or(defaults.capacity),
+            refill_interval_milliseconds: self
+                .refill_interval_milliseconds
+                .unwrap_or(defaults.refill_interval_milliseconds),
+        }
+    }
+}
+
+/// Checks on the token embedded in the subscription form of the home page.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct BotProtectionSettings {
+    pub enabled: bool,
+    /// Submissions sent sooner than this after the form was served are rejected.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_submit_seconds: u64,
+    /// Submissions sent later than this after the form was served are rejected.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_token_age_seconds: u64,
+}
+
+impl Default for BotProtectionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_submit_seconds: 3,
+            max_token_age_seconds: 86_400,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct IdempotencySettings {
+    /// Responses are replayed for that long, after which the key can be reused.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    /// A request still in progress after that long is assumed to have crashed,
+    /// and a retry takes over its key.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub in_progress_timeout_seconds: u64,
+    /// How long a retry waits for the request in progress to complete,
+    /// before giving up with a 409.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub in_progress_wait_milliseconds: u64,
+    /// How often expired entries are deleted.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub sweep_interval_seconds: u64,
+}
+
+impl Default for IdempotencySettings {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 86_400,
+            in_progress_timeout_seconds: 60,
+            in_progress_wait_milliseconds: 5_000,
+            sweep_interval_seconds: 3_600,
+        }
+    }
+}
+
+impl IdempotencySettings {
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn in_progress_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.in_progress_timeout_seconds)
+    }
+
+    pub fn in_progress_wait(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.in_progress_wait_milliseconds)
+    }
+
+    pub fn sweep_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.sweep_interval_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct AttachmentSettings {
+    /// Larger files are rejected at upload.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_file_bytes: usize,
+    /// The files attached to an issue may not add up to more than that.
+    /// Postmark rejects messages over 10 MB, attachments are base64-encoded.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_total_bytes: usize,
+}
+
+impl Default for AttachmentSettings {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 5_000_000,
+            max_total_bytes: 7_000_000,
+        }
+    }
+}
+
+/// The Atom and RSS feeds of the public archive.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct FeedSettings {
+    pub title: String,
+    pub description: String,
+    /// The most recent issues are listed, older ones remain in the web archive.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_entries: i64,
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        Self {
+            title: "Our newsletter".into(),
+            description: "Past issues of our newsletter.".into(),
+            max_entries: 20,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct ConfirmationSettings {
+    /// Confirmation links stop working after that long, the subscriber can ask for a new one.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub link_ttl_hours: i64,
+    pub welcome_email: WelcomeEmailSettings,
+}
+
+impl Default for ConfirmationSettings {
+    fn default() -> Self {
+        Self {
+            link_ttl_hours: 72,
+            welcome_email: WelcomeEmailSettings::default(),
+        }
+    }
+}
+
+/// Sent once a subscriber confirms. The bodies are templates that can use the
+/// merge fields of newsletter issues, and are wrapped in the default layout.
+#[derive(serde::Deserialize, Clone)]
+#[serde(default)]
+pub struct WelcomeEmailSettings {
+    pub enabled: bool,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl Default for WelcomeEmailSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            subject: "Welcome aboard!".into(),
+            html_body: "<p>Hi {{ name }}, thanks for confirming your subscription!</p>".into(),
+            text_body: "Hi {{ name }}, thanks for confirming your subscription!".into(),
+        }
+    }
+}
+
+/// Access to `/metrics`, which is not served unless a token is set.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct MetricsSettings {
+    /// Scrapers send it as `Authorization: Bearer <token>`.
+    #[serde(default)]
+    pub bearer_token: Option<SecretString>,
+}
+
 pub enum Environment {
     Local,
     Production,
@@ -71,6 +392,17 @@ pub struct EmailClientSettings {
 }
 
 impl EmailClientSettings {
+    pub fn client(self) -> Result<EmailClient, anyhow::Error> {
+        let sender_email = self.sender().map_err(anyhow::Error::msg)?;
+        let timeout = self.timeout();
+        Ok(EmailClient::new(
+            self.base_url.parse()?,
+            sender_email,
+            self.authorization_token,
+            timeout,
+        ))
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(&self.sender_email)
     }
@@ -119,3 +451,99 @@ impl DatabaseSettings {
         options
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AttachmentSettings, BotProtectionSettings, ConfirmationSettings, FeedSettings,
+        IdempotencySettings, RateLimitSettings, ReadinessSettings,
+    };
+
+    #[test]
+    fn rate_limit_settings_can_be_partially_overridden() {
+        let settings: RateLimitSettings = serde_json::from_value(serde_json::json!({
+            "trust_forwarded_for": true,
+            "per_email": { "capacity": "5" }
+        }))
+        .unwrap();
+
+        assert!(settings.enabled);
+        assert_eq!(settings.key_prefix, "rate_limit");
+        assert!(settings.trust_forwarded_for);
+        assert_eq!(settings.per_ip.capacity, 10);
+        assert_eq!(settings.per_ip.refill_interval_milliseconds, 6_000);
+        assert_eq!(settings.per_email.capacity, 5);
+        assert_eq!(settings.per_email.refill_interval_milliseconds, 1_200_000);
+    }
+
+    #[test]
+    fn readiness_settings_can_be_partially_overridden() {
+        let settings: ReadinessSettings = serde_json::from_value(serde_json::json!({
+            "check_timeout_milliseconds": "500"
+        }))
+        .unwrap();
+
+        assert_eq!(settings.max_worker_heartbeat_age_seconds, 60);
+        assert_eq!(settings.check_timeout_milliseconds, 500);
+    }
+
+    #[test]
+    fn bot_protection_settings_can_be_partially_overridden() {
+        let settings: BotProtectionSettings = serde_json::from_value(serde_json::json!({
+            "enabled": false
+        }))
+        .unwrap();
+
+        assert!(!settings.enabled);
+        assert_eq!(settings.min_submit_seconds, 3);
+        assert_eq!(settings.max_token_age_seconds, 86_400);
+    }
+
+    #[test]
+    fn idempotency_settings_can_be_partially_overridden() {
+        let settings: IdempotencySettings = serde_json::from_value(serde_json::json!({
+            "ttl_seconds": "3600"
+        }))
+        .unwrap();
+
+        assert_eq!(settings.ttl_seconds, 3_600);
+        assert_eq!(settings.in_progress_timeout_seconds, 60);
+        assert_eq!(settings.in_progress_wait_milliseconds, 5_000);
+        assert_eq!(settings.sweep_interval_seconds, 3_600);
+    }
+
+    #[test]
+    fn attachment_settings_can_be_partially_overridden() {
+        let settings: AttachmentSettings = serde_json::from_value(serde_json::json!({
+            "max_file_bytes": "1000"
+        }))
+        .unwrap();
+
+        assert_eq!(settings.max_file_bytes, 1_000);
+        assert_eq!(settings.max_total_bytes, 7_000_000);
+    }
+
+    #[test]
+    fn feed_settings_can_be_partially_overridden() {
+        let settings: FeedSettings = serde_json::from_value(serde_json::json!({
+            "title": "Weekly news"
+        }))
+        .unwrap();
+
+        assert_eq!(settings.title, "Weekly news");
+        assert_eq!(settings.description, "Past issues of our newsletter.");
+        assert_eq!(settings.max_entries, 20);
+    }
+
+    #[test]
+    fn confirmation_settings_can_be_partially_overridden() {
+        let settings: ConfirmationSettings = serde_json::from_value(serde_json::json!({
+            "welcome_email": { "enabled": true }
+        }))
+        .unwrap();
+
+        assert_eq!(settings.link_ttl_hours, 72);
+        assert!(settings.welcome_email.enabled);
+        assert_eq!(settings.welcome_email.subject, "Welcome aboard!");
+    }
+}
@@ -0,0 +1,270 @@
+//! Confirmation emails are written to an outbox in the transaction that stores the
+//! subscriber, and sent by the background worker: subscribing does not wait on the
+//! email provider, and no confirmation is lost if it is down.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{Instrument, Span, field::display};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+use crate::configuration::EmailLayoutSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_layout::resolve_layout;
+use crate::issue_delivery_worker::ExecutionOutcome;
+use crate::suppression::find_suppression;
+use crate::telemetry::TraceContext;
+use crate::templating::ContentKind;
+
+/// Emails that still fail after that many attempts are dropped.
+const MAX_ATTEMPTS: i32 = 8;
+/// Doubled after each failed attempt.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3_600);
+
+#[tracing::instrument(
+    name = "Enqueue a confirmation email",
+    skip(transaction, subscription_token)
+)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
+    let trace_context = TraceContext::current();
+    sqlx::query!(
+        r#"
+            INSERT INTO confirmation_email_outbox (
+                outbox_id,
+                subscriber_id,
+                subscription_token,
+                next_attempt_at,
+                created_at,
+                traceparent,
+                tracestate
+            )
+            VALUES ($1, $2, $3, now(), now(), $4, $5)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        subscription_token,
+        trace_context.traceparent,
+        trace_context.tracestate,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Number of confirmation emails waiting to be sent.
+#[tracing::instrument(skip_all)]
+pub async fn outbox_depth(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT count(*) AS "count!" FROM confirmation_email_outbox"#)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(row.count)
+}
+
+/// How long to wait before the next attempt, after `n_attempts` failed ones.
+fn retry_delay(n_attempts: i32) -> Duration {
+    let exponent = u32::try_from(n_attempts.saturating_sub(1)).unwrap_or_default();
+    FIRST_RETRY_DELAY
+        .checked_mul(2u32.saturating_pow(exponent))
+        .unwrap_or(MAX_RETRY_DELAY)
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Send the oldest confirmation email that is due. A failed attempt is scheduled for a
+/// retry rather than reported as an error, the outbox entry being updated either way.
+#[tracing::instrument(
+    skip_all,
+    fields(outbox_id=tracing::field::Empty, subscriber_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email_layout: &EmailLayoutSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let entry = sqlx::query!(
+        r#"
+            SELECT
+                o.outbox_id,
+                o.subscriber_id,
+                o.subscription_token,
+                o.n_attempts,
+                o.traceparent,
+                o.tracestate,
+                s.email
+            FROM confirmation_email_outbox o
+            JOIN subscriptions s ON s.id = o.subscriber_id
+            WHERE o.next_attempt_at <= now()
+            ORDER BY o.next_attempt_at
+            FOR UPDATE OF o
+            SKIP LOCKED
+            LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(entry) = entry else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("outbox_id", display(entry.outbox_id))
+        .record("subscriber_id", display(entry.subscriber_id));
+
+    // Unlike an issue delivery, the email is sent on behalf of a single request: it belongs
+    // to the same trace.
+    let send_span = tracing::info_span!("Send a confirmation email to a new subscriber");
+    let trace_context = TraceContext {
+        traceparent: entry.traceparent,
+        tracestate: entry.tracestate,
+    };
+    if trace_context.span_context().is_valid() {
+        let _ = send_span.set_parent(trace_context.parent_context());
+    }
+    let outcome = match SubscriberEmail::parse(&entry.email) {
+        Ok(recipient) => {
+            send_confirmation_email(
+                pool,
+                email_client,
+                &recipient,
+                base_url,
+                &entry.subscription_token,
+                email_layout,
+            )
+            .instrument(send_span)
+            .await
+        }
+        Err(e) => Err(anyhow::anyhow!(e)),
+    };
+    let n_attempts = entry.n_attempts + 1;
+    match outcome {
+        Ok(()) => {
+            delete_entry(&mut transaction, entry.outbox_id).await?;
+        }
+        Err(e) if n_attempts >= MAX_ATTEMPTS => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                n_attempts,
+                "Giving up on a confirmation email",
+            );
+            delete_entry(&mut transaction, entry.outbox_id).await?;
+        }
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                n_attempts,
+                "Failed to send a confirmation email, it will be retried",
+            );
+            let retry_delay = retry_delay(n_attempts).as_secs_f64();
+            sqlx::query!(
+                r#"
+                    UPDATE confirmation_email_outbox
+                    SET
+                        n_attempts = $2,
+                        next_attempt_at = now() + make_interval(secs => $3),
+                        last_error = $4
+                    WHERE outbox_id = $1
+                "#,
+                entry.outbox_id,
+                n_attempts,
+                retry_delay,
+                format!("{e:#}"),
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to update the confirmation email outbox")?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn delete_entry(
+    transaction: &mut Transaction<'_, Postgres>,
+    outbox_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM confirmation_email_outbox WHERE outbox_id = $1",
+        outbox_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+async fn send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    subscription_token: &str,
+    email_layout: &EmailLayoutSettings,
+) -> Result<(), anyhow::Error> {
+    if let Some(suppression) = find_suppression(pool, recipient.as_ref())
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!(
+            suppression.reason = %suppression.reason,
+            "Not sending a confirmation email to a suppressed address",
+        );
+        return Ok(());
+    }
+
+    let confirmation_link =
+        format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
+    let plain_body = format!(
+        "Welcome to our newsletter!\nVisit {confirmation_link} to confirm your subscription."
+    );
+
+    let html_body = format!(
+        "Welcome to our newsletter!<br />\
+            Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription."
+    );
+    let layout = resolve_layout(pool, None, email_layout)
+        .await
+        .context("Failed to look up the email layout.")?;
+    let html_body = layout
+        .wrap(&html_body, ContentKind::Html, None)
+        .context("Failed to render the HTML layout.")?;
+    let plain_body = layout
+        .wrap(&plain_body, ContentKind::Text, None)
+        .context("Failed to render the text layout.")?;
+
+    email_client
+        .send_email(recipient, "Welcome!", &html_body, &plain_body, &[])
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::retry_delay;
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(2), Duration::from_secs(60));
+        assert_eq!(retry_delay(3), Duration::from_secs(120));
+        assert_eq!(retry_delay(8), Duration::from_secs(3_600));
+        assert_eq!(retry_delay(100), Duration::from_secs(3_600));
+    }
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::{DeliveryOutcome, IssueStatus};
+
+/// One attempt at emailing an issue to a recipient.
+pub struct Delivery {
+    pub delivery_id: Uuid,
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Option<Uuid>,
+    pub subscriber_email: String,
+    pub outcome: String,
+    pub provider_message_id: Option<String>,
+    pub error: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+/// A delivery attempt to be added to the log.
+#[derive(Debug)]
+pub struct NewDelivery<'a> {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Option<Uuid>,
+    pub subscriber_email: &'a str,
+    pub outcome: DeliveryOutcome,
+    /// The id assigned by the email provider to an email it accepted.
+    pub provider_message_id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Meant to run in the transaction that dequeued the task: the attempt is
+/// timestamped with the start of the transaction.
+#[tracing::instrument(name = "Record newsletter delivery", skip(executor))]
+pub async fn record_delivery(
+    executor: impl PgExecutor<'_>,
+    delivery: &NewDelivery<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO newsletter_deliveries (
+                delivery_id,
+                newsletter_issue_id,
+                subscriber_id,
+                subscriber_email,
+                outcome,
+                provider_message_id,
+                error,
+                attempted_at,
+                completed_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now(), clock_timestamp())
+        "#,
+        Uuid::new_v4(),
+        delivery.newsletter_issue_id,
+        delivery.subscriber_id,
+        delivery.subscriber_email,
+        delivery.outcome.as_str(),
+        delivery.provider_message_id,
+        delivery.error
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "List deliveries of a newsletter issue", skip(pool))]
+pub async fn get_issue_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+            SELECT
+                delivery_id,
+                newsletter_issue_id,
+                subscriber_id,
+                subscriber_email,
+                outcome,
+                provider_message_id,
+                error,
+                attempted_at,
+                completed_at
+            FROM newsletter_deliveries
+            WHERE newsletter_issue_id = $1
+            ORDER BY completed_at
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "List deliveries to a subscriber", skip(pool))]
+pub async fn get_subscriber_deliveries(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+            SELECT
+                delivery_id,
+                newsletter_issue_id,
+                subscriber_id,
+                subscriber_email,
+                outcome,
+                provider_message_id,
+                error,
+                attempted_at,
+                completed_at
+            FROM newsletter_deliveries
+            WHERE subscriber_id = $1
+            ORDER BY completed_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The number of emails of an issue sent so far, and of those that failed.
+pub struct IssueDeliveryCounts {
+    pub newsletter_issue_id: Uuid,
+    pub n_sent: i64,
+    pub n_failed: i64,
+}
+
+/// Counts for the issues being sent and those completed in the last day, which
+/// keeps the number of issues reported bounded.
+#[tracing::instrument(name = "Count deliveries of recent newsletter issues", skip(pool))]
+pub async fn get_recent_issue_delivery_counts(
+    pool: &PgPool,
+) -> Result<Vec<IssueDeliveryCounts>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueDeliveryCounts,
+        r#"
+            SELECT
+                i.newsletter_issue_id,
+                count(*) FILTER (WHERE d.outcome = $1) AS "n_sent!",
+                count(*) FILTER (WHERE d.outcome = $2) AS "n_failed!"
+            FROM newsletter_issues i
+            LEFT JOIN newsletter_deliveries d
+                ON d.newsletter_issue_id = i.newsletter_issue_id
+            WHERE i.status IN ($3, $4) OR i.completed_at > now() - interval '1 day'
+            GROUP BY i.newsletter_issue_id
+        "#,
+        DeliveryOutcome::Sent.as_str(),
+        DeliveryOutcome::Failed.as_str(),
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+    )
+    .fetch_all(pool)
+    .await
+}
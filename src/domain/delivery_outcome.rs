@@ -0,0 +1,64 @@
+/// What happened to an email queued for a newsletter issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// Accepted by the email provider.
+    Sent,
+    Failed,
+    /// The recipient is on the suppression list.
+    Suppressed,
+    /// The recipient is no longer subscribed.
+    Skipped,
+    /// The issue was cancelled before the email went out.
+    Cancelled,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Sent => "sent",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Suppressed => "suppressed",
+            DeliveryOutcome::Skipped => "skipped",
+            DeliveryOutcome::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryOutcome {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "sent" => Ok(Self::Sent),
+            "failed" => Ok(Self::Failed),
+            "suppressed" => Ok(Self::Suppressed),
+            "skipped" => Ok(Self::Skipped),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{other} is not a valid delivery outcome.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_err;
+
+    use super::DeliveryOutcome;
+
+    #[test]
+    fn outcomes_round_trip_through_their_name() {
+        for outcome in [
+            DeliveryOutcome::Sent,
+            DeliveryOutcome::Failed,
+            DeliveryOutcome::Suppressed,
+            DeliveryOutcome::Skipped,
+            DeliveryOutcome::Cancelled,
+        ] {
+            assert_eq!(
+                DeliveryOutcome::try_from(outcome.as_str().to_owned()),
+                Ok(outcome)
+            );
+        }
+        assert_err!(DeliveryOutcome::try_from("bounced".to_owned()));
+    }
+}
@@ -1,7 +1,13 @@
+mod delivery_outcome;
 mod new_subscriber;
+mod newsletter_issue;
 mod subscriber_email;
 mod subscriber_name;
+mod suppression;
 
+pub use delivery_outcome::DeliveryOutcome;
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_issue::{IssueContent, IssueStatus, NewsletterIssue};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use suppression::{SuppressionReason, SuppressionTarget};
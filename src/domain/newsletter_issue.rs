@@ -0,0 +1,321 @@
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+use crate::{
+    email_layout::ResolvedLayout,
+    html::check_structure,
+    templating::{ContentKind, links_to_archive, links_to_unsubscribe, validate},
+};
+
+const MAX_TITLE_LENGTH: usize = 256;
+/// In bytes. Gmail clips messages larger than about 100 KB, leave room for the layout.
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+const MAX_SLUG_TITLE_LENGTH: usize = 60;
+
+/// Where a newsletter issue is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueStatus {
+    Draft,
+    Scheduled,
+    /// Published, some emails are still queued.
+    Sending,
+    /// Held back by an administrator, the remaining emails wait in the queue.
+    Paused,
+    /// Every recipient has been processed.
+    Sent,
+    Cancelled,
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Draft => "draft",
+            IssueStatus::Scheduled => "scheduled",
+            IssueStatus::Sending => "sending",
+            IssueStatus::Paused => "paused",
+            IssueStatus::Sent => "sent",
+            IssueStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for IssueStatus {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "draft" => Ok(Self::Draft),
+            "scheduled" => Ok(Self::Scheduled),
+            "sending" => Ok(Self::Sending),
+            "paused" => Ok(Self::Paused),
+            "sent" => Ok(Self::Sent),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{other} is not a valid issue status.")),
+        }
+    }
+}
+
+/// The bodies of a newsletter issue. When it is written in Markdown, both are rendered from it.
+#[derive(Debug)]
+pub struct IssueContent {
+    pub text: String,
+    pub html: String,
+    pub markdown: Option<String>,
+}
+
+/// A newsletter issue that is fit to be sent.
+#[derive(Debug)]
+pub struct NewsletterIssue {
+    title: String,
+    content: IssueContent,
+}
+
+impl NewsletterIssue {
+    /// `layout` is the layout the issue will be sent with: the bodies must link to
+    /// `{{ unsubscribe_url }}`, unless it does.
+    pub fn parse(
+        title: &str,
+        content: IssueContent,
+        layout: &ResolvedLayout,
+    ) -> Result<Self, String> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err("The title cannot be empty.".into());
+        }
+        if title.graphemes(true).count() > MAX_TITLE_LENGTH {
+            return Err(format!(
+                "The title cannot be longer than {MAX_TITLE_LENGTH} characters."
+            ));
+        }
+        for (body, kind) in [
+            (&content.text, ContentKind::Text),
+            (&content.html, ContentKind::Html),
+        ] {
+            let label = match kind {
+                ContentKind::Text => "text",
+                ContentKind::Html => "HTML",
+            };
+            if body.trim().is_empty() {
+                return Err(format!("The {label} content cannot be empty."));
+            }
+            if body.len() > MAX_BODY_LENGTH {
+                return Err(format!(
+                    "The {label} content cannot be larger than {} KB.",
+                    MAX_BODY_LENGTH / 1024
+                ));
+            }
+            validate(body, kind)
+                .map_err(|e| format!("The {label} content is not a valid template: {e}"))?;
+            if let ContentKind::Html = kind {
+                check_structure(body)
+                    .map_err(|e| format!("The HTML content is not well-formed: {e}"))?;
+            }
+            if !links_to_unsubscribe(body, kind) && !layout.links_to_unsubscribe(kind) {
+                return Err(format!(
+                    "The {label} content must link to {{{{ unsubscribe_url }}}}, \
+                    the layout does not."
+                ));
+            }
+        }
+
+        Ok(Self {
+            title: title.to_owned(),
+            content,
+        })
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn content(&self) -> &IssueContent {
+        &self.content
+    }
+
+    /// Whether either body uses `{{ view_in_browser_url }}`: only issues published to
+    /// the archive can be viewed in a browser.
+    pub fn links_to_archive(&self) -> bool {
+        links_to_archive(&self.content.text, ContentKind::Text)
+            || links_to_archive(&self.content.html, ContentKind::Html)
+    }
+
+    /// The path segment of the issue in the public archive, e.g. `issue-1-0f3c9a2e`: the
+    /// title keeps the URL readable, the start of the issue id keeps it unique.
+    pub fn slug(&self, issue_id: Uuid) -> String {
+        let mut slug = String::new();
+        for c in self.title.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        slug.truncate(MAX_SLUG_TITLE_LENGTH);
+        let slug = slug.trim_matches('-');
+        let id = &issue_id.simple().to_string()[..8];
+        if slug.is_empty() {
+            id.to_owned()
+        } else {
+            format!("{slug}-{id}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use uuid::Uuid;
+
+    use super::{IssueContent, IssueStatus, NewsletterIssue};
+    use crate::email_layout::{BUILT_IN_HTML_TEMPLATE, BUILT_IN_TEXT_TEMPLATE, ResolvedLayout};
+
+    #[test]
+    fn statuses_round_trip_through_their_name() {
+        for status in [
+            IssueStatus::Draft,
+            IssueStatus::Scheduled,
+            IssueStatus::Sending,
+            IssueStatus::Paused,
+            IssueStatus::Sent,
+            IssueStatus::Cancelled,
+        ] {
+            assert_eq!(
+                IssueStatus::try_from(status.as_str().to_owned()),
+                Ok(status)
+            );
+        }
+        assert_err!(IssueStatus::try_from("published".to_owned()));
+    }
+
+    fn built_in_layout() -> ResolvedLayout {
+        ResolvedLayout::new(
+            BUILT_IN_HTML_TEMPLATE.into(),
+            BUILT_IN_TEXT_TEMPLATE.into(),
+            String::new(),
+        )
+    }
+
+    fn bare_layout() -> ResolvedLayout {
+        ResolvedLayout::new(
+            "{{ content }}".into(),
+            "{{ content }}".into(),
+            String::new(),
+        )
+    }
+
+    fn content(text: &str, html: &str) -> IssueContent {
+        IssueContent {
+            text: text.into(),
+            html: html.into(),
+            markdown: None,
+        }
+    }
+
+    #[test]
+    fn a_valid_issue_is_parsed_successfully() {
+        let issue = NewsletterIssue::parse(
+            " Issue #1 ",
+            content("Hi {{ name }}!", "<p>Hi {{ name }}!</p>"),
+            &built_in_layout(),
+        )
+        .unwrap();
+        assert_eq!(issue.title(), "Issue #1");
+    }
+
+    #[test]
+    fn empty_or_overlong_titles_are_rejected() {
+        for title in ["", "  ", &"a".repeat(257)] {
+            assert_err!(NewsletterIssue::parse(
+                title,
+                content("Body", "<p>Body</p>"),
+                &built_in_layout()
+            ));
+        }
+        assert_ok!(NewsletterIssue::parse(
+            &"a".repeat(256),
+            content("Body", "<p>Body</p>"),
+            &built_in_layout()
+        ));
+    }
+
+    #[test]
+    fn empty_or_oversized_bodies_are_rejected() {
+        let huge = "a".repeat(64 * 1024 + 1);
+        for (text, html) in [
+            ("", "<p>Body</p>"),
+            ("Body", " "),
+            (huge.as_str(), "<p>Body</p>"),
+        ] {
+            assert_err!(NewsletterIssue::parse(
+                "Title",
+                content(text, html),
+                &built_in_layout()
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_templates_and_malformed_html_are_rejected() {
+        let e = NewsletterIssue::parse(
+            "Title",
+            content("Hi {{ name", "<p>Body</p>"),
+            &built_in_layout(),
+        )
+        .err()
+        .unwrap();
+        assert!(e.starts_with("The text content is not a valid template"));
+        let e = NewsletterIssue::parse(
+            "Title",
+            content("Body", "<div><p>Body</div></p>"),
+            &built_in_layout(),
+        )
+        .err()
+        .unwrap();
+        assert!(e.starts_with("The HTML content is not well-formed"));
+    }
+
+    #[test]
+    fn slugs_are_built_from_the_title_and_the_issue_id() {
+        let issue_id = Uuid::parse_str("0f3c9a2e-6b1d-4c8e-9f00-123456789abc").unwrap();
+        for (title, slug) in [
+            ("Issue #1: What's new?", "issue-1-what-s-new-0f3c9a2e"),
+            ("  Déjà vu  ", "d-j-vu-0f3c9a2e"),
+            ("Новости", "0f3c9a2e"),
+        ] {
+            let issue =
+                NewsletterIssue::parse(title, content("Body", "<p>Body</p>"), &built_in_layout())
+                    .unwrap();
+            assert_eq!(issue.slug(issue_id), slug);
+        }
+        let issue = NewsletterIssue::parse(
+            &"a".repeat(100),
+            content("Body", "<p>Body</p>"),
+            &built_in_layout(),
+        )
+        .unwrap();
+        assert_eq!(issue.slug(issue_id), format!("{}-0f3c9a2e", "a".repeat(60)));
+    }
+
+    #[test]
+    fn an_unsubscribe_link_is_required_unless_the_layout_has_one() {
+        let without_link = || content("Body", "<p>Body</p>");
+        let with_link = || {
+            content(
+                "Body {{ unsubscribe_url }}",
+                "<a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>",
+            )
+        };
+        assert_err!(NewsletterIssue::parse(
+            "Title",
+            without_link(),
+            &bare_layout()
+        ));
+        assert_ok!(NewsletterIssue::parse("Title", with_link(), &bare_layout()));
+        assert_ok!(NewsletterIssue::parse(
+            "Title",
+            without_link(),
+            &built_in_layout()
+        ));
+    }
+}
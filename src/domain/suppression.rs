@@ -0,0 +1,149 @@
+use super::SubscriberEmail;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    Manual,
+    Bounce,
+    Complaint,
+    Unsubscribe,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Manual => "manual",
+            SuppressionReason::Bounce => "bounce",
+            SuppressionReason::Complaint => "complaint",
+            SuppressionReason::Unsubscribe => "unsubscribe",
+        }
+    }
+}
+
+impl TryFrom<String> for SuppressionReason {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "manual" => Ok(Self::Manual),
+            "bounce" => Ok(Self::Bounce),
+            "complaint" => Ok(Self::Complaint),
+            "unsubscribe" => Ok(Self::Unsubscribe),
+            other => Err(format!("{other} is not a valid suppression reason.")),
+        }
+    }
+}
+
+/// What a suppression entry matches: a single address or every address of a domain.
+#[derive(Debug)]
+pub enum SuppressionTarget {
+    Email(SubscriberEmail),
+    Domain(String),
+}
+
+impl SuppressionTarget {
+    /// Anything containing an `@` with a local part is treated as an email address,
+    /// otherwise the input is expected to be a domain (a leading `@` is tolerated).
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        match s.split_once('@') {
+            Some((local_part, _)) if !local_part.is_empty() => {
+                SubscriberEmail::parse(s).map(Self::Email)
+            }
+            Some((_, domain)) => Self::parse_domain(domain),
+            None => Self::parse_domain(s),
+        }
+    }
+
+    fn parse_domain(s: &str) -> Result<Self, String> {
+        let domain = s.to_lowercase();
+        let labels: Vec<&str> = domain.split('.').collect();
+        let is_valid = labels.len() > 1
+            && labels.iter().all(|label| {
+                !label.is_empty()
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if is_valid {
+            Ok(Self::Domain(domain))
+        } else {
+            Err(format!("{s} is not a valid email address or domain."))
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SuppressionTarget::Email(_) => "email",
+            SuppressionTarget::Domain(_) => "domain",
+        }
+    }
+
+    /// The normalised value stored in (and matched against) the suppression list.
+    pub fn value(&self) -> String {
+        match self {
+            SuppressionTarget::Email(email) => email.as_ref().to_lowercase(),
+            SuppressionTarget::Domain(domain) => domain.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{SuppressionReason, SuppressionTarget};
+
+    #[test]
+    fn an_email_address_is_parsed_as_an_email_target() {
+        let target = SuppressionTarget::parse("Ursula@Example.com").unwrap();
+        assert_eq!(target.kind(), "email");
+        assert_eq!(target.value(), "ursula@example.com");
+    }
+
+    #[test]
+    fn a_bare_domain_is_parsed_as_a_domain_target() {
+        let target = SuppressionTarget::parse("Example.com").unwrap();
+        assert_eq!(target.kind(), "domain");
+        assert_eq!(target.value(), "example.com");
+    }
+
+    #[test]
+    fn a_domain_with_a_leading_at_is_parsed_as_a_domain_target() {
+        let target = SuppressionTarget::parse("@example.com").unwrap();
+        assert_eq!(target.kind(), "domain");
+        assert_eq!(target.value(), "example.com");
+    }
+
+    #[test]
+    fn invalid_targets_are_rejected() {
+        for input in [
+            "",
+            " ",
+            "localhost",
+            "exa mple.com",
+            "-example.com",
+            "example..com",
+        ] {
+            assert_err!(SuppressionTarget::parse(input));
+        }
+    }
+
+    #[test]
+    fn an_invalid_email_address_is_rejected() {
+        assert_err!(SuppressionTarget::parse("ursula@"));
+    }
+
+    #[test]
+    fn known_reasons_are_parsed() {
+        for reason in ["manual", "bounce", "complaint", "unsubscribe"] {
+            let parsed = SuppressionReason::try_from(reason.to_owned());
+            assert_ok!(&parsed);
+            assert_eq!(parsed.unwrap().as_str(), reason);
+        }
+    }
+
+    #[test]
+    fn unknown_reasons_are_rejected() {
+        assert_err!(SuppressionReason::try_from("spam".to_owned()));
+    }
+}
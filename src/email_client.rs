@@ -1,7 +1,11 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, SecretString};
 
 use crate::domain::SubscriberEmail;
+use crate::metrics::METRICS;
+use crate::telemetry::TraceContext;
 
 #[derive(Clone)]
 pub struct EmailClient {
@@ -19,6 +23,34 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<AttachmentRequest<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct AttachmentRequest<'a> {
+    name: &'a str,
+    /// Base64-encoded.
+    content: String,
+    content_type: &'a str,
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+/// A file sent along with an email.
+pub struct EmailAttachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+    /// Set for inline images, which the HTML body references as `cid:<content_id>`.
+    pub content_id: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
 }
 
 impl EmailClient {
@@ -40,13 +72,15 @@ impl EmailClient {
         }
     }
 
+    /// Returns the id Postmark assigned to the message, if it reported one.
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+        attachments: &[EmailAttachment],
+    ) -> Result<Option<String>, reqwest::Error> {
         let url = self
             .base_url
             .join("email")
@@ -57,19 +91,49 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            attachments: attachments
+                .iter()
+                .map(|a| AttachmentRequest {
+                    name: &a.file_name,
+                    content: BASE64.encode(&a.content),
+                    content_type: &a.content_type,
+                    content_id: a.content_id.as_ref().map(|id| format!("cid:{id}")),
+                })
+                .collect(),
         };
-        self.http_client
-            .post(url)
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
+        let mut request = self.http_client.post(url).header(
+            "X-Postmark-Server-Token",
+            self.authorization_token.expose_secret(),
+        );
+        let trace_context = TraceContext::current();
+        if let Some(traceparent) = trace_context.traceparent {
+            request = request.header("traceparent", traceparent);
+        }
+        if let Some(tracestate) = trace_context.tracestate {
+            request = request.header("tracestate", tracestate);
+        }
+        let start = std::time::Instant::now();
+        let outcome = request
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await
+            .and_then(|response| response.error_for_status());
+        METRICS
+            .email_client_request_duration_seconds
+            .with_label_values(&[if outcome.is_ok() {
+                "success"
+            } else {
+                "failure"
+            }])
+            .observe(start.elapsed().as_secs_f64());
+        // The email was accepted, an unexpected body does not make it a failure.
+        let message_id = outcome?
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .and_then(|response| response.message_id);
 
-        Ok(())
+        Ok(message_id)
     }
 }
 
@@ -90,7 +154,7 @@ mod tests {
         matchers::{any, header, header_exists, method, path},
     };
 
-    use super::EmailClient;
+    use super::{EmailAttachment, EmailClient};
     use crate::domain::SubscriberEmail;
 
     struct SendEmailBodyMatcher;
@@ -149,7 +213,7 @@ mod tests {
 
         // Act
         let _ = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Assert
@@ -169,11 +233,89 @@ mod tests {
 
         // Act
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_reported_by_the_server() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_eq!(
+            outcome.unwrap().as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_attachments_and_inline_images() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let attachments = [
+            EmailAttachment {
+                file_name: "notes.txt".into(),
+                content_type: "text/plain".into(),
+                content: b"Hello!".to_vec(),
+                content_id: None,
+            },
+            EmailAttachment {
+                file_name: "logo.png".into(),
+                content_type: "image/png".into(),
+                content: vec![0x89, b'P', b'N', b'G'],
+                content_id: Some("logo".into()),
+            },
+        ];
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &attachments)
             .await;
 
         // Assert
         assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Attachments"],
+            serde_json::json!([
+                {"Name": "notes.txt", "Content": "SGVsbG8h", "ContentType": "text/plain"},
+                {
+                    "Name": "logo.png",
+                    "Content": "iVBORw==",
+                    "ContentType": "image/png",
+                    "ContentID": "cid:logo"
+                }
+            ])
+        );
     }
 
     #[tokio::test]
@@ -190,7 +332,7 @@ mod tests {
 
         // Act
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Assert
@@ -212,7 +354,7 @@ mod tests {
 
         // Act
         let outcome = email_client
-            .send_email(&email(), &subject(), &content(), &content())
+            .send_email(&email(), &subject(), &content(), &content(), &[])
             .await;
 
         // Assert
@@ -0,0 +1,222 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::configuration::EmailLayoutSettings;
+use crate::domain::IssueStatus;
+use crate::templating::{ContentKind, MergeFields, links_to_unsubscribe, render_layout};
+
+/// Used when no admin-managed layout applies.
+pub const BUILT_IN_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta http-equiv="content-type" content="text/html; charset=utf-8"></head>
+<body>
+{% if view_in_browser_url %}<p><a href="{{ view_in_browser_url }}">View in browser</a></p>
+{% endif %}{{ content }}
+{% if unsubscribe_url %}<hr>
+<p><a href="{{ unsubscribe_url }}">Unsubscribe</a> | <a href="{{ preferences_url }}">Manage your subscription</a></p>
+{% endif %}{% if physical_address %}<p>{{ physical_address }}</p>
+{% endif %}</body>
+</html>"#;
+
+pub const BUILT_IN_TEXT_TEMPLATE: &str = "{{ content }}\
+{% if unsubscribe_url %}\n\n--\n\
+{% if view_in_browser_url %}View in browser: {{ view_in_browser_url }}\n{% endif %}\
+Unsubscribe: {{ unsubscribe_url }}\n\
+Manage your subscription: {{ preferences_url }}{% endif %}\
+{% if physical_address %}\n\n{{ physical_address }}{% endif %}";
+
+pub struct EmailLayout {
+    pub layout_id: Uuid,
+    pub name: String,
+    pub html_template: String,
+    pub text_template: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The layout that applies to an email, ready to wrap its bodies.
+pub struct ResolvedLayout {
+    html_template: String,
+    text_template: String,
+    physical_address: String,
+}
+
+impl ResolvedLayout {
+    pub fn new(html_template: String, text_template: String, physical_address: String) -> Self {
+        Self {
+            html_template,
+            text_template,
+            physical_address,
+        }
+    }
+
+    pub fn wrap(
+        &self,
+        body: &str,
+        kind: ContentKind,
+        fields: Option<&MergeFields>,
+    ) -> Result<String, minijinja::Error> {
+        let template = match kind {
+            ContentKind::Html => &self.html_template,
+            ContentKind::Text => &self.text_template,
+        };
+        render_layout(template, kind, body, &self.physical_address, fields)
+    }
+
+    pub fn links_to_unsubscribe(&self, kind: ContentKind) -> bool {
+        match kind {
+            ContentKind::Html => links_to_unsubscribe(&self.html_template, kind),
+            ContentKind::Text => links_to_unsubscribe(&self.text_template, kind),
+        }
+    }
+}
+
+/// Pick the layout with `layout_id` if given, then the configured default layout,
+/// then the built-in one.
+#[tracing::instrument(name = "Resolve email layout", skip(executor, settings))]
+pub async fn resolve_layout(
+    executor: impl PgExecutor<'_>,
+    layout_id: Option<Uuid>,
+    settings: &EmailLayoutSettings,
+) -> Result<ResolvedLayout, sqlx::Error> {
+    let layout = sqlx::query!(
+        r#"
+            SELECT html_template, text_template
+            FROM email_layouts
+            WHERE layout_id = $1 OR name = $2
+            ORDER BY (layout_id = $1) IS TRUE DESC
+            LIMIT 1
+        "#,
+        layout_id,
+        settings.default_layout
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    let (html_template, text_template) = match layout {
+        Some(l) => (l.html_template, l.text_template),
+        None => (
+            BUILT_IN_HTML_TEMPLATE.to_owned(),
+            BUILT_IN_TEXT_TEMPLATE.to_owned(),
+        ),
+    };
+    Ok(ResolvedLayout::new(
+        html_template,
+        text_template,
+        settings.physical_address.clone(),
+    ))
+}
+
+#[tracing::instrument(name = "Check that an email layout exists", skip(executor))]
+pub async fn layout_exists(
+    executor: impl PgExecutor<'_>,
+    layout_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT 1 AS "found!"
+            FROM email_layouts
+            WHERE layout_id = $1
+        "#,
+        layout_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+/// Whether issues still being sent, or on display in the archive, use the layout.
+#[tracing::instrument(name = "Check whether an email layout is in use", skip(executor))]
+pub async fn layout_in_use(
+    executor: impl PgExecutor<'_>,
+    layout_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT 1 AS "found!"
+            FROM newsletter_issues
+            WHERE layout_id = $1
+                AND status <> $2
+                AND (status <> $3 OR archived)
+            LIMIT 1
+        "#,
+        layout_id,
+        IssueStatus::Cancelled.as_str(),
+        IssueStatus::Sent.as_str(),
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+#[tracing::instrument(name = "List email layouts", skip(pool))]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<EmailLayout>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailLayout,
+        r#"
+            SELECT layout_id, name, html_template, text_template, created_at
+            FROM email_layouts
+            ORDER BY name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if a layout with the same name already exists.
+#[tracing::instrument(
+    name = "Add email layout",
+    skip(executor, html_template, text_template)
+)]
+pub async fn insert_layout(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+    html_template: &str,
+    text_template: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+            INSERT INTO email_layouts (
+                layout_id,
+                name,
+                html_template,
+                text_template,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, now())
+            ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        html_template,
+        text_template
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(n_inserted_rows > 0)
+}
+
+/// Issues that still reference the layout fall back to the default one, so check
+/// [`layout_in_use`] first.
+#[tracing::instrument(name = "Delete email layout", skip(executor))]
+pub async fn delete_layout(
+    executor: impl PgExecutor<'_>,
+    layout_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+            DELETE FROM email_layouts
+            WHERE layout_id = $1
+        "#,
+        layout_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{Rng, distr::Alphanumeric, rng};
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::configuration::BotProtectionSettings;
+
+/// Keeps a signature issued for one form from being accepted by another.
+const PURPOSE: &str = "subscribe";
+
+/// Why a subscription form submission was turned down.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum FormTokenError {
+    #[error("The form could not be verified, please reload the page and try again.")]
+    Missing,
+    #[error("The form could not be verified, please reload the page and try again.")]
+    Invalid,
+    #[error("The form was submitted too quickly, please try again.")]
+    TooFast,
+    #[error("The form has expired, please reload the page and try again.")]
+    Expired,
+    #[error("The form has already been submitted.")]
+    Replayed,
+}
+
+impl FormTokenError {
+    /// Label of the rejected submissions counter.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            FormTokenError::Missing => "missing_token",
+            FormTokenError::Invalid => "invalid_token",
+            FormTokenError::TooFast => "too_fast",
+            FormTokenError::Expired => "expired_token",
+            FormTokenError::Replayed => "replayed_token",
+        }
+    }
+}
+
+/// A token whose signature and age have been checked, but that may have been used already.
+pub struct VerifiedToken {
+    nonce: String,
+}
+
+/// Issues and verifies the signed, time-stamped tokens embedded in the
+/// subscription form, formatted as `<issued at>.<nonce>.<signature>`.
+pub struct FormTokens {
+    secret: SecretString,
+    settings: BotProtectionSettings,
+    /// Where used tokens are remembered.
+    used_tokens: Option<ConnectionManager>,
+}
+
+impl FormTokens {
+    pub fn new(secret: SecretString, settings: BotProtectionSettings) -> Self {
+        Self {
+            secret,
+            settings,
+            used_tokens: None,
+        }
+    }
+
+    /// Reject tokens that have already been used, see [`FormTokens::mark_as_used`].
+    pub fn with_replay_protection(mut self, connection: ConnectionManager) -> Self {
+        self.used_tokens = Some(connection);
+        self
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    pub fn issue(&self) -> String {
+        self.issue_at(Utc::now())
+    }
+
+    pub fn issue_at(&self, issued_at: DateTime<Utc>) -> String {
+        let mut rng = rng();
+        let nonce: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(22)
+            .collect();
+        let payload = format!("{}.{nonce}", issued_at.timestamp());
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    pub fn verify(&self, token: &str) -> Result<VerifiedToken, FormTokenError> {
+        if token.trim().is_empty() {
+            return Err(FormTokenError::Missing);
+        }
+        let (payload, signature) = token.rsplit_once('.').ok_or(FormTokenError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| FormTokenError::Invalid)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| FormTokenError::Invalid)?;
+
+        let (issued_at, nonce) = payload.split_once('.').ok_or(FormTokenError::Invalid)?;
+        let issued_at: i64 = issued_at.parse().map_err(|_| FormTokenError::Invalid)?;
+        let age = Utc::now().timestamp() - issued_at;
+        if age < self.settings.min_submit_seconds as i64 {
+            return Err(FormTokenError::TooFast);
+        }
+        if age > self.settings.max_token_age_seconds as i64 {
+            return Err(FormTokenError::Expired);
+        }
+
+        Ok(VerifiedToken {
+            nonce: nonce.to_owned(),
+        })
+    }
+
+    /// Record that the token has been used, failing if it was already.
+    ///
+    /// Tokens are remembered until they expire. Like rate limiting, the check is
+    /// skipped if Redis is unavailable.
+    #[tracing::instrument(name = "Mark form token as used", skip_all)]
+    pub async fn mark_as_used(&self, token: &VerifiedToken) -> Result<(), FormTokenError> {
+        let Some(connection) = &self.used_tokens else {
+            return Ok(());
+        };
+        let outcome: Result<Option<String>, _> = redis::cmd("SET")
+            .arg(format!("form_token:{}", token.nonce))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.settings.max_token_age_seconds.max(1))
+            .query_async(&mut connection.clone())
+            .await;
+        match outcome {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(FormTokenError::Replayed),
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to check whether the form token was used, accepting it."
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<sha2::Sha256> {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(self.secret.expose_secret().as_bytes()).unwrap();
+        mac.update(PURPOSE.as_bytes());
+        mac.update(b":");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use secrecy::SecretString;
+
+    use super::{FormTokenError, FormTokens};
+    use crate::configuration::BotProtectionSettings;
+
+    fn form_tokens(secret: &str) -> FormTokens {
+        FormTokens::new(
+            SecretString::from(secret),
+            BotProtectionSettings {
+                enabled: true,
+                min_submit_seconds: 3,
+                max_token_age_seconds: 3600,
+            },
+        )
+    }
+
+    #[test]
+    fn a_token_is_accepted_between_the_minimum_and_maximum_age() {
+        let tokens = form_tokens("secret");
+        let token = tokens.issue_at(Utc::now() - Duration::seconds(10));
+        assert!(tokens.verify(&token).is_ok());
+    }
+
+    #[test]
+    fn tokens_submitted_too_early_or_too_late_are_rejected() {
+        let tokens = form_tokens("secret");
+        let fresh = tokens.issue();
+        let stale = tokens.issue_at(Utc::now() - Duration::hours(2));
+        assert_eq!(tokens.verify(&fresh).err(), Some(FormTokenError::TooFast));
+        assert_eq!(tokens.verify(&stale).err(), Some(FormTokenError::Expired));
+    }
+
+    #[test]
+    fn tampered_or_foreign_tokens_are_rejected() {
+        let tokens = form_tokens("secret");
+        let token = tokens.issue_at(Utc::now() - Duration::seconds(10));
+        let (timestamp, rest) = token.split_once('.').unwrap();
+        let tampered = format!("{}.{rest}", timestamp.parse::<i64>().unwrap() - 1);
+        let foreign = form_tokens("another secret").issue_at(Utc::now() - Duration::seconds(10));
+
+        for token in [tampered, foreign, "garbage".into()] {
+            assert_eq!(tokens.verify(&token).err(), Some(FormTokenError::Invalid));
+        }
+        assert_eq!(tokens.verify("").err(), Some(FormTokenError::Missing));
+    }
+}
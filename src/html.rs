@@ -0,0 +1,220 @@
+use actix_web_flash_messages::IncomingFlashMessages;
+pub use htmlize::{escape_attribute, escape_text};
+
+use crate::configuration::HtmlSanitizerSettings;
+
+/// Tags that are always removed together with their content, whatever the allow-list says.
+const ALWAYS_REMOVED_TAGS: [&str; 2] = ["script", "style"];
+
+/// Render flash messages as HTML. They often echo user input back, so they are escaped.
+pub fn flash_messages_html(flash_messages: &IncomingFlashMessages) -> String {
+    let mut html = String::new();
+    for m in flash_messages.iter() {
+        html.push_str(&format!("<p><i>{}</i></p>\n", escape_text(m.content())));
+    }
+    html
+}
+
+/// Strip everything outside of the allow-list from an HTML email body: scripts,
+/// event handlers, `javascript:` links and so on.
+///
+/// `cid:` links are kept, they point to the inline images attached to the email.
+pub fn sanitize(html: &str, settings: &HtmlSanitizerSettings) -> String {
+    ammonia::Builder::default()
+        .add_url_schemes(["cid"])
+        .add_tags(
+            settings
+                .extra_tags
+                .iter()
+                .filter(|tag| !ALWAYS_REMOVED_TAGS.contains(&tag.as_str())),
+        )
+        .add_generic_attributes(&settings.extra_attributes)
+        .clean(html)
+        .to_string()
+}
+
+/// Elements that never have an end tag.
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements whose end tag may be omitted, e.g. `<li>` closed by the next item.
+const OPTIONAL_END_TAG_ELEMENTS: [&str; 18] = [
+    "body", "colgroup", "dd", "dt", "head", "html", "li", "optgroup", "option", "p", "rp", "rt",
+    "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+/// Check that the elements of `html` are closed, and closed in the right order.
+///
+/// This catches truncated or mangled markup, it is no substitute for a validator:
+/// the end tags HTML allows to omit are not required.
+pub fn check_structure(html: &str) -> Result<(), String> {
+    let mut open: Vec<String> = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").ok_or("A comment is never closed.")?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').ok_or("A declaration is never closed.")?;
+            rest = &rest[end + 1..];
+            continue;
+        }
+        let (is_end_tag, tag) = match rest.strip_prefix("</") {
+            Some(tag) => (true, tag),
+            None => (false, &rest[1..]),
+        };
+        if !tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            // A literal `<`, e.g. in `1 < 2`.
+            rest = &rest[1..];
+            continue;
+        }
+        let name_length = tag
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .unwrap_or(tag.len());
+        let name = tag[..name_length].to_ascii_lowercase();
+        let attributes_length = tag_end(&tag[name_length..])
+            .ok_or_else(|| format!("The <{name}> tag is never closed."))?;
+        let is_self_closing = tag[..name_length + attributes_length].ends_with('/');
+        rest = &tag[name_length + attributes_length + 1..];
+
+        if is_end_tag {
+            close(&mut open, &name)?;
+        } else if !VOID_ELEMENTS.contains(&name.as_str()) && !is_self_closing {
+            if name == "script" || name == "style" {
+                // Their content is not markup, skip to the end tag.
+                let end = rest
+                    .to_ascii_lowercase()
+                    .find(&format!("</{name}"))
+                    .ok_or_else(|| format!("<{name}> is never closed."))?;
+                rest = &rest[end..];
+            }
+            open.push(name);
+        }
+    }
+    match open
+        .iter()
+        .find(|name| !OPTIONAL_END_TAG_ELEMENTS.contains(&name.as_str()))
+    {
+        Some(name) => Err(format!("<{name}> is never closed.")),
+        None => Ok(()),
+    }
+}
+
+/// The position of the `>` ending a tag, skipping over quoted attribute values.
+fn tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn close(open: &mut Vec<String>, name: &str) -> Result<(), String> {
+    let position = open
+        .iter()
+        .rposition(|n| n == name)
+        .ok_or_else(|| format!("</{name}> does not close any element."))?;
+    if let Some(unclosed) = open[position + 1..]
+        .iter()
+        .find(|n| !OPTIONAL_END_TAG_ELEMENTS.contains(&n.as_str()))
+    {
+        return Err(format!("<{unclosed}> must be closed before </{name}>."));
+    }
+    open.truncate(position);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{check_structure, sanitize};
+    use crate::configuration::HtmlSanitizerSettings;
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let html = sanitize(
+            "<p onclick=\"steal()\">Hi</p><script>alert(1)</script>\
+            <a href=\"javascript:alert(1)\">link</a>",
+            &HtmlSanitizerSettings::default(),
+        );
+        assert_eq!(html, "<p>Hi</p><a rel=\"noopener noreferrer\">link</a>");
+    }
+
+    #[test]
+    fn links_to_the_recipient_urls_are_kept() {
+        let html = sanitize(
+            "<a href=\"https://example.com/subscriptions/unsubscribe?subscription_token=abc\">x</a>",
+            &HtmlSanitizerSettings::default(),
+        );
+        assert!(html.contains(
+            "href=\"https://example.com/subscriptions/unsubscribe?subscription_token=abc\""
+        ));
+    }
+
+    #[test]
+    fn inline_image_references_are_kept() {
+        let html = sanitize(
+            "<img src=\"cid:0b6bd0d8\" alt=\"Logo\">",
+            &HtmlSanitizerSettings::default(),
+        );
+        assert_eq!(html, "<img src=\"cid:0b6bd0d8\" alt=\"Logo\">");
+    }
+
+    #[test]
+    fn the_allow_list_can_be_extended() {
+        let settings = HtmlSanitizerSettings {
+            extra_tags: vec!["center".into()],
+            extra_attributes: vec!["style".into()],
+        };
+        let html = sanitize("<center style=\"color: red\">Hi</center>", &settings);
+        assert_eq!(html, "<center style=\"color: red\">Hi</center>");
+    }
+
+    #[test]
+    fn scripts_cannot_be_allowed() {
+        let settings = HtmlSanitizerSettings {
+            extra_tags: vec!["script".into()],
+            extra_attributes: vec![],
+        };
+        assert_eq!(sanitize("<script>alert(1)</script>Hi", &settings), "Hi");
+    }
+
+    #[test]
+    fn well_formed_html_passes_the_structure_check() {
+        for html in [
+            "<p>Newsletter body as HTML</p>",
+            "<!DOCTYPE html><html><body><h1>Hi {{ name }}</h1></body></html>",
+            "<ul><li>One<li>Two</ul><p>First<p>Second",
+            "<p>Line<br>break<img src=\"x.png\" alt=\"a > b\"/></p>",
+            "<!-- <div> --><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>",
+            "<p>1 < 2</p>",
+            "<script>if (a<b) {}</script><STRONG>Hi</strong>",
+        ] {
+            assert_ok!(check_structure(html), "{html}");
+        }
+    }
+
+    #[test]
+    fn malformed_html_fails_the_structure_check() {
+        for html in [
+            "<div><p>Unclosed",
+            "<b><i>Crossed</b></i>",
+            "Stray</div>",
+            "<a href=\"https://example.com\"",
+            "<!-- Unclosed comment",
+        ] {
+            assert_err!(check_structure(html), "{html}");
+        }
+    }
+}
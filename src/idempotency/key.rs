@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IdempotencyKey(String);
 impl TryFrom<String> for IdempotencyKey {
     type Error = anyhow::Error;
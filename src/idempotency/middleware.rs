@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use actix_web::{
+    HttpMessage, HttpResponse, ResponseError,
+    body::BoxBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::ErrorUnprocessableEntity,
+    http::{
+        Method, StatusCode,
+        header::{self, HeaderName, HeaderValue},
+    },
+    middleware::Next,
+    mime, web,
+};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{
+    authentication::UserId,
+    configuration::IdempotencySettings,
+    idempotency::{IdempotencyKey, NextAction, release, save_response, try_processing},
+    rate_limit::client_ip,
+    utils::{e400, e500},
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+/// Set on responses that were replayed rather than produced by the handler.
+pub const IDEMPOTENT_REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Marks a response that must not be replayed, e.g. because the request was
+/// rejected before doing anything: retrying it with the same key runs the handler again.
+///
+/// Error responses are never replayed.
+pub fn not_replayable(mut response: HttpResponse) -> HttpResponse {
+    response.extensions_mut().insert(NotReplayable);
+    response
+}
+
+struct NotReplayable;
+
+/// The idempotency key processed by the current request, found in its extensions.
+///
+/// Handlers that commit a transaction save their response in it with
+/// [`save_response`](Self::save_response): otherwise a failure between the commit
+/// and the save lets a retry process the request again.
+#[derive(Clone)]
+pub struct IdempotencyClaim {
+    key: IdempotencyKey,
+    scope: String,
+}
+
+impl IdempotencyClaim {
+    pub async fn save_response(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        response: HttpResponse,
+    ) -> Result<HttpResponse, anyhow::Error> {
+        let mut response = save_response(&mut **transaction, &self.key, &self.scope, response)
+            .await
+            .context("Failed to save the response for the idempotency key")?;
+        response.extensions_mut().insert(ResponseSaved);
+        Ok(response)
+    }
+}
+
+struct ResponseSaved;
+
+/// How often a request waiting for another one with the same key checks on it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Another request with the same key did not complete in time.
+#[derive(Debug)]
+pub struct RequestInProgress;
+
+impl std::fmt::Display for RequestInProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A request with the same idempotency key is still being processed, please try again later."
+        )
+    }
+}
+
+impl std::error::Error for RequestInProgress {}
+
+impl ResponseError for RequestInProgress {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::CONFLICT
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header((header::RETRY_AFTER, "1"))
+            .body(self.to_string())
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct KeyField {
+    idempotency_key: Option<String>,
+}
+
+/// Process each idempotency key at most once, replaying the saved response on retries.
+///
+/// The key is read from the `Idempotency-Key` header or, failing that, from the
+/// `idempotency_key` form field. Requests without one are processed as usual.
+/// Keys are scoped to the logged-in user or, on anonymous routes, to the client
+/// address as found by [`client_ip`]: keys are ignored if it is unknown.
+/// Reusing a key for a different request is rejected with a 422.
+///
+/// A retry arriving while the original request is in progress waits for its
+/// response, or takes over if it fails, and gives up with a 409 after a while.
+pub async fn enforce_idempotency(
+    mut req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if req.method().is_safe() {
+        return next.call(req).await;
+    }
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(Payload::from(body.clone()));
+    let Some(idempotency_key) = idempotency_key(&req, &body)? else {
+        return next.call(req).await;
+    };
+    let user_id = req.extensions().get::<UserId>().cloned();
+    let scope = match user_id {
+        Some(user_id) => format!("user:{user_id}"),
+        None => match client_ip(req.request()) {
+            Some(ip) => format!("client:{ip}"),
+            None => {
+                // Keys of unrelated clients would collide in a shared scope.
+                tracing::warn!("The client address is unknown, the idempotency key is ignored.");
+                return next.call(req).await;
+            }
+        },
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .context("The database pool is not configured")
+        .map_err(e500)?;
+    let settings = req
+        .app_data::<web::Data<IdempotencySettings>>()
+        .cloned()
+        .context("The idempotency settings are not configured")
+        .map_err(e500)?;
+
+    let fingerprint = fingerprint(req.method(), &req.uri().to_string(), &body);
+    let deadline = tokio::time::Instant::now() + settings.in_progress_wait();
+    loop {
+        match try_processing(&pool, &idempotency_key, &scope, &fingerprint, &settings)
+            .await
+            .map_err(e500)?
+        {
+            NextAction::StartProcessing => break,
+            NextAction::ReturnSavedResponse(mut saved_response) => {
+                saved_response
+                    .headers_mut()
+                    .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+                return Ok(req.into_response(saved_response));
+            }
+            NextAction::RejectKeyReuse => {
+                return Err(ErrorUnprocessableEntity(
+                    "The idempotency key was already used for a different request.",
+                ));
+            }
+            NextAction::WaitForCompletion => {
+                if tokio::time::Instant::now() + POLL_INTERVAL > deadline {
+                    return Err(RequestInProgress.into());
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+
+    req.extensions_mut().insert(IdempotencyClaim {
+        key: idempotency_key.clone(),
+        scope: scope.clone(),
+    });
+    let outcome = next.call(req).await;
+    match outcome {
+        Ok(response)
+            if response
+                .response()
+                .extensions()
+                .get::<ResponseSaved>()
+                .is_some() =>
+        {
+            Ok(response)
+        }
+        Ok(response) if is_replayable(response.response()) => {
+            let (req, response) = response.into_parts();
+            let response = save_response(&**pool, &idempotency_key, &scope, response)
+                .await
+                .map_err(e500)?;
+            Ok(ServiceResponse::new(req, response))
+        }
+        outcome => {
+            if let Err(e) = release(&pool, &idempotency_key, &scope).await {
+                // The key is taken over once the request is considered stale.
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to release the idempotency key."
+                );
+            }
+            outcome
+        }
+    }
+}
+
+fn idempotency_key(
+    req: &ServiceRequest,
+    body: &[u8],
+) -> Result<Option<IdempotencyKey>, actix_web::Error> {
+    let key = if let Some(value) = req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value.to_str().map_err(e400)?.to_owned())
+    } else if req.content_type() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str() {
+        serde_urlencoded::from_bytes::<KeyField>(body)
+            .map_err(e400)?
+            .idempotency_key
+    } else {
+        None
+    };
+    key.map(IdempotencyKey::try_from).transpose().map_err(e400)
+}
+
+fn fingerprint(method: &Method, uri: &str, body: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b" ");
+    hasher.update(uri);
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher.finalize().to_vec()
+}
+
+fn is_replayable(response: &HttpResponse) -> bool {
+    !response.status().is_client_error()
+        && !response.status().is_server_error()
+        && response.extensions().get::<NotReplayable>().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{ResponseError, http::Method};
+
+    use super::{RequestInProgress, fingerprint};
+
+    #[test]
+    fn requests_in_progress_are_rejected_with_a_conflict_to_retry() {
+        let response = RequestInProgress.error_response();
+        assert_eq!(response.status().as_u16(), 409);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "1");
+    }
+
+    #[test]
+    fn requests_differing_in_method_target_or_body_have_different_fingerprints() {
+        let reference = fingerprint(&Method::POST, "/subscribe", b"name=le%20guin");
+        assert_eq!(
+            reference,
+            fingerprint(&Method::POST, "/subscribe", b"name=le%20guin")
+        );
+        for other in [
+            fingerprint(&Method::PUT, "/subscribe", b"name=le%20guin"),
+            fingerprint(&Method::POST, "/subscribe?a=b", b"name=le%20guin"),
+            fingerprint(&Method::POST, "/subscribe", b"name=ursula"),
+        ] {
+            assert_ne!(reference, other);
+        }
+    }
+}
@@ -1,5 +1,9 @@
 mod key;
+mod middleware;
 mod persistence;
+mod sweeper;
 
 pub use key::IdempotencyKey;
+pub use middleware::*;
 pub use persistence::*;
+pub use sweeper::run_sweeper_until_stopped;
@@ -1,8 +1,9 @@
+use std::time::Duration;
+
 use actix_web::{HttpResponse, body::to_bytes, http::StatusCode};
-use sqlx::{PgPool, Postgres, Transaction};
-use uuid::Uuid;
+use sqlx::{PgExecutor, PgPool};
 
-use crate::idempotency::IdempotencyKey;
+use crate::{configuration::IdempotencySettings, idempotency::IdempotencyKey};
 
 #[derive(sqlx::Type, Debug)]
 #[sqlx(type_name = "header_pair")]
@@ -12,14 +13,18 @@ struct HeaderPairRecord {
 }
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    StartProcessing,
     ReturnSavedResponse(HttpResponse),
+    /// The key was used for a different request.
+    RejectKeyReuse,
+    /// Another request with the same key has not completed yet.
+    WaitForCompletion,
 }
 
 pub async fn get_saved_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &str,
 ) -> Result<Option<HttpResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"
@@ -29,10 +34,11 @@ pub async fn get_saved_response(
                 response_body as "response_body!"
             FROM idempotency
             WHERE
-                user_id = $1 AND
-                idempotency_key = $2
+                scope = $1 AND
+                idempotency_key = $2 AND
+                response_status_code IS NOT NULL
         "#,
-        user_id,
+        scope,
         idempotency_key.as_ref()
     )
     .fetch_optional(pool)
@@ -51,9 +57,9 @@ pub async fn get_saved_response(
 }
 
 pub async fn save_response(
-    mut transaction: Transaction<'static, Postgres>,
+    executor: impl PgExecutor<'_>,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &str,
     http_response: HttpResponse,
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
@@ -79,57 +85,132 @@ pub async fn save_response(
                 response_headers = $4,
                 response_body = $5
             WHERE
-                user_id = $1 AND
+                scope = $1 AND
                 idempotency_key = $2
         "#,
-        user_id,
+        scope,
         idempotency_key.as_ref(),
         status_code,
         headers,
         body.as_ref()
     )
-    .execute(&mut *transaction)
+    .execute(executor)
     .await?;
-    transaction.commit().await?;
 
     let http_response = response_head.set_body(body).map_into_boxed_body();
 
     Ok(http_response)
 }
 
+/// Claim `idempotency_key`, unless a response was saved for it or another
+/// request is processing it.
+///
+/// Keys whose response has expired, and keys whose request has been in progress
+/// for longer than it should (e.g. because the instance crashed), are taken over.
+#[tracing::instrument(name = "Claim idempotency key", skip(pool, fingerprint, settings))]
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &str,
+    fingerprint: &[u8],
+    settings: &IdempotencySettings,
 ) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = pool.begin().await?;
-    sqlx::query!("SET TRANSACTION ISOLATION LEVEL repeatable read")
-        .execute(&mut *transaction)
-        .await?;
     let n_inserted_rows = sqlx::query!(
         r#"
             INSERT INTO idempotency (
-                user_id,
+                scope,
                 idempotency_key,
+                request_fingerprint,
                 created_at
             )
-            VALUES ($1, $2, now())
-            ON CONFLICT DO NOTHING
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (scope, idempotency_key) DO UPDATE
+            SET
+                request_fingerprint = EXCLUDED.request_fingerprint,
+                response_status_code = NULL,
+                response_headers = NULL,
+                response_body = NULL,
+                created_at = now()
+            WHERE
+                idempotency.created_at < now() - make_interval(secs => $4) OR (
+                    idempotency.response_status_code IS NULL AND
+                    idempotency.created_at < now() - make_interval(secs => $5)
+                )
         "#,
-        user_id,
-        idempotency_key.as_ref()
+        scope,
+        idempotency_key.as_ref(),
+        fingerprint,
+        settings.ttl().as_secs_f64(),
+        settings.in_progress_timeout().as_secs_f64(),
     )
-    .execute(&mut *transaction)
+    .execute(pool)
     .await?
     .rows_affected();
 
     if n_inserted_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
-    } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
-
-        Ok(NextAction::ReturnSavedResponse(saved_response))
+        return Ok(NextAction::StartProcessing);
+    }
+    let saved_fingerprint = sqlx::query_scalar!(
+        r#"
+            SELECT request_fingerprint
+            FROM idempotency
+            WHERE
+                scope = $1 AND
+                idempotency_key = $2
+        "#,
+        scope,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+    // Entries saved before requests were fingerprinted match any request.
+    if saved_fingerprint.is_some_and(|saved| saved != fingerprint) {
+        return Ok(NextAction::RejectKeyReuse);
+    }
+    match get_saved_response(pool, idempotency_key, scope).await? {
+        Some(saved_response) => Ok(NextAction::ReturnSavedResponse(saved_response)),
+        None => Ok(NextAction::WaitForCompletion),
     }
 }
+
+/// Give up the claim on `idempotency_key`, so that the request can be retried.
+#[tracing::instrument(name = "Release idempotency key", skip(pool))]
+pub async fn release(
+    pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    scope: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            DELETE FROM idempotency
+            WHERE
+                scope = $1 AND
+                idempotency_key = $2 AND
+                response_status_code IS NULL
+        "#,
+        scope,
+        idempotency_key.as_ref()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the number of entries deleted.
+#[tracing::instrument(name = "Delete expired idempotency entries", skip(pool))]
+pub async fn delete_expired_entries(pool: &PgPool, ttl: Duration) -> Result<u64, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+            DELETE FROM idempotency
+            WHERE created_at < now() - make_interval(secs => $1)
+        "#,
+        ttl.as_secs_f64()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows)
+}
@@ -0,0 +1,24 @@
+use sqlx::PgPool;
+
+use crate::{
+    configuration::{IdempotencySettings, Settings},
+    idempotency::delete_expired_entries,
+    startup::get_connection_pool,
+};
+
+async fn sweeper_loop(pool: PgPool, settings: IdempotencySettings) -> Result<(), anyhow::Error> {
+    let mut interval = tokio::time::interval(settings.sweep_interval());
+    loop {
+        interval.tick().await;
+        match delete_expired_entries(&pool, settings.ttl()).await {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Deleted {n} expired idempotency entries"),
+            Err(e) => tracing::error!(error.cause_chain = ?e, error.message = %e),
+        }
+    }
+}
+
+pub async fn run_sweeper_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    sweeper_loop(connection_pool, configuration.idempotency).await
+}
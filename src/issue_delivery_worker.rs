@@ -1,74 +1,197 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use sqlx::{PgPool, Postgres, Transaction};
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
 use tracing::{Span, field::display};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use uuid::Uuid;
 
-use crate::configuration::Settings;
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::attachment::get_issue_attachments;
+use crate::configuration::{EmailLayoutSettings, HtmlSanitizerSettings, Settings};
+use crate::confirmation_outbox::try_send_confirmation_email;
+use crate::delivery_log::{NewDelivery, record_delivery};
+use crate::domain::{DeliveryOutcome, IssueStatus, SubscriberEmail};
+use crate::email_client::{EmailAttachment, EmailClient};
+use crate::email_layout::{ResolvedLayout, resolve_layout};
+use crate::html::sanitize;
+use crate::metrics::METRICS;
 use crate::startup::get_connection_pool;
+use crate::suppression::find_suppression;
+use crate::telemetry::TraceContext;
+use crate::templating::{ContentKind, MergeFields, render};
 
 #[tracing::instrument(
     skip_all,
     fields(
     newsletter_issue_id=tracing::field::Empty,
+    subscriber_id=tracing::field::Empty,
     subscriber_email=tracing::field::Empty
     ),
     err
     )]
-async fn try_execute_task(
+pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    email_layout: &EmailLayoutSettings,
+    html_sanitizer: &HtmlSanitizerSettings,
+    attachment_cache: &mut AttachmentCache,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
     let task = dequeue_task(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
-    if let Some((transaction, issue_id, email)) = dequeue_task(pool).await? {
-        Span::current()
-            .record("newsletter_issue_id", display(issue_id))
-            .record("subscriber_email", display(&email));
-        match SubscriberEmail::parse(&email) {
-            Ok(email) => {
-                let issue = get_issue(pool, issue_id).await?;
-                if let Err(e) = email_client
-                    .send_email(
-                        &email,
-                        &issue.title,
-                        &issue.html_content,
-                        &issue.text_content,
-                    )
-                    .await
-                {
-                    tracing::error!(error.cause_chain = ?e, error.message = %e);
-                }
+    let (mut transaction, issue_id, subscriber_id, trace_context) = task.unwrap();
+    let span = Span::current();
+    span.record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_id", display(subscriber_id));
+    span.add_link(trace_context.span_context());
+    // The current details of the subscriber, who cannot be deleted while the task is locked.
+    let recipient = get_recipient(&mut *transaction, subscriber_id).await?;
+    span.record("subscriber_email", display(&recipient.email));
+    let mut delivery = NewDelivery {
+        newsletter_issue_id: issue_id,
+        subscriber_id: Some(subscriber_id),
+        subscriber_email: &recipient.email,
+        outcome: DeliveryOutcome::Skipped,
+        provider_message_id: None,
+        error: None,
+    };
+    if recipient.status != "confirmed" {
+        tracing::info!(
+            subscriber.status = %recipient.status,
+            "Skipping a recipient who is no longer subscribed",
+        );
+    } else if let Some(suppression) = find_suppression(pool, &recipient.email).await? {
+        tracing::info!(
+            suppression.reason = %suppression.reason,
+            "Skipping a suppressed recipient",
+        );
+        delivery.outcome = DeliveryOutcome::Suppressed;
+    } else if let Some(subscription_token) = &recipient.subscription_token {
+        let issue = get_issue(pool, issue_id).await?;
+        let layout = resolve_layout(pool, issue.layout_id, email_layout).await?;
+        let attachments = attachment_cache.get(pool, issue_id).await?;
+        let mut fields = MergeFields::new(&recipient.name, base_url, subscription_token);
+        if issue.archived {
+            fields.view_in_browser_url = Some(format!("{base_url}/archive/{}", issue.slug));
+        }
+        match send_issue(
+            email_client,
+            &recipient.email,
+            &issue,
+            &layout,
+            html_sanitizer,
+            &fields,
+            attachments,
+        )
+        .await
+        {
+            Ok(provider_message_id) => {
+                METRICS.emails_sent_total.inc();
+                delivery.outcome = DeliveryOutcome::Sent;
+                delivery.provider_message_id = provider_message_id;
             }
             Err(e) => {
+                METRICS.emails_failed_total.inc();
                 tracing::error!(error.cause_chain = ?e, error.message = %e);
+                delivery.outcome = DeliveryOutcome::Failed;
+                delivery.error = Some(format!("{e:#}"));
             }
         }
-        delete_task(transaction, issue_id, &email).await?;
+    } else {
+        tracing::warn!("Skipping a recipient without a subscription token");
     }
+    record_delivery(&mut *transaction, &delivery)
+        .await
+        .context("Failed to record the delivery")?;
+    delete_task(transaction, issue_id, subscriber_id).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// The attachments of the issue delivered last, loaded once rather than for each
+/// recipient: those of an issue do not change once it is published.
+#[derive(Default)]
+pub struct AttachmentCache {
+    issue: Option<(Uuid, Vec<EmailAttachment>)>,
+}
+
+impl AttachmentCache {
+    async fn get(
+        &mut self,
+        pool: &PgPool,
+        issue_id: Uuid,
+    ) -> Result<&[EmailAttachment], sqlx::Error> {
+        if self
+            .issue
+            .as_ref()
+            .is_none_or(|(cached_issue_id, _)| *cached_issue_id != issue_id)
+        {
+            let attachments = get_issue_attachments(pool, issue_id).await?;
+            self.issue = Some((issue_id, attachments));
+        }
+        Ok(self
+            .issue
+            .as_ref()
+            .map_or(&[], |(_, attachments)| attachments.as_slice()))
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn send_issue(
+    email_client: &EmailClient,
+    recipient: &str,
+    issue: &NewsletterIssue,
+    layout: &ResolvedLayout,
+    html_sanitizer: &HtmlSanitizerSettings,
+    fields: &MergeFields,
+    attachments: &[EmailAttachment],
+) -> Result<Option<String>, anyhow::Error> {
+    let html_content = render(&issue.html_content, ContentKind::Html, fields)
+        .context("Failed to render the HTML content of the issue")?;
+    // Sanitize the rendered body rather than the template, so that no template
+    // construct can smuggle markup past the allow-list.
+    let html_content = sanitize(&html_content, html_sanitizer);
+    let text_content = render(&issue.text_content, ContentKind::Text, fields)
+        .context("Failed to render the text content of the issue")?;
+    let html_content = layout
+        .wrap(&html_content, ContentKind::Html, Some(fields))
+        .context("Failed to render the HTML layout")?;
+    let text_content = layout
+        .wrap(&text_content, ContentKind::Text, Some(fields))
+        .context("Failed to render the text layout")?;
+    let recipient = SubscriberEmail::parse(recipient).map_err(anyhow::Error::msg)?;
+    let provider_message_id = email_client
+        .send_email(
+            &recipient,
+            &issue.title,
+            &html_content,
+            &text_content,
+            attachments,
+        )
+        .await?;
+
+    Ok(provider_message_id)
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+) -> Result<Option<(PgTransaction, Uuid, Uuid, TraceContext)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let r = sqlx::query!(
         r#"
-            SELECT newsletter_issue_id, subscriber_email
-            FROM issue_delivery_queue
-            FOR UPDATE
+            SELECT q.newsletter_issue_id, q.subscriber_id, q.traceparent, q.tracestate
+            FROM issue_delivery_queue q
+            JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+            WHERE i.status = $1
+            FOR UPDATE OF q
             SKIP LOCKED
             LIMIT 1
         "#,
+        IssueStatus::Sending.as_str(),
     )
     .fetch_optional(&mut *transaction)
     .await?;
@@ -76,7 +199,11 @@ async fn dequeue_task(
         Ok(Some((
             transaction,
             r.newsletter_issue_id,
-            r.subscriber_email,
+            r.subscriber_id,
+            TraceContext {
+                traceparent: r.traceparent,
+                tracestate: r.tracestate,
+            },
         )))
     } else {
         Ok(None)
@@ -87,28 +214,126 @@ async fn dequeue_task(
 async fn delete_task(
     mut transaction: PgTransaction,
     issue_id: Uuid,
-    email: &str,
+    subscriber_id: Uuid,
 ) -> Result<(), anyhow::Error> {
+    // Workers delivering the last emails of an issue take turns, so that
+    // whichever deletes the last task sees the others' deletions.
+    sqlx::query!(
+        r#"
+            SELECT 1 AS "locked"
+            FROM newsletter_issues
+            WHERE newsletter_issue_id = $1
+            FOR NO KEY UPDATE
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
     sqlx::query!(
         r#"
             DELETE FROM issue_delivery_queue
             WHERE
             newsletter_issue_id = $1 AND
-            subscriber_email = $2
+            subscriber_id = $2
         "#,
         issue_id,
-        email
+        subscriber_id
     )
     .execute(&mut *transaction)
     .await?;
+    complete_issue_if_delivered(&mut transaction, issue_id).await?;
     transaction.commit().await?;
     Ok(())
 }
 
+/// Mark a sending issue as sent once no email is left in the queue for it.
+#[tracing::instrument(skip(transaction))]
+pub async fn complete_issue_if_delivered(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2, completed_at = now()
+            WHERE
+                newsletter_issue_id = $1 AND
+                status = $3 AND
+                NOT EXISTS (
+                    SELECT 1
+                    FROM issue_delivery_queue
+                    WHERE newsletter_issue_id = $1
+                )
+        "#,
+        issue_id,
+        IssueStatus::Sent.as_str(),
+        IssueStatus::Sending.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Number of emails waiting to be delivered, across all issues.
+#[tracing::instrument(skip_all)]
+pub async fn queue_depth(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(row.count)
+}
+
+/// Let readiness checks know that a worker is alive, and forget about workers
+/// that have been gone for a day.
+#[tracing::instrument(skip(pool))]
+pub async fn record_heartbeat(pool: &PgPool, worker_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO worker_heartbeats (worker_id, last_seen_at)
+            VALUES ($1, now())
+            ON CONFLICT (worker_id) DO UPDATE SET last_seen_at = now()
+        "#,
+        worker_id
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        r#"
+            DELETE FROM worker_heartbeats
+            WHERE last_seen_at < now() - interval '1 day'
+        "#
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Time since the most recent heartbeat of any worker, `None` if there is none.
+#[tracing::instrument(skip_all)]
+pub async fn latest_heartbeat_age(pool: &PgPool) -> Result<Option<Duration>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            SELECT EXTRACT(EPOCH FROM now() - max(last_seen_at))::float8 AS age_seconds
+            FROM worker_heartbeats
+        "#
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row
+        .age_seconds
+        .map(|age| Duration::from_secs_f64(age.max(0.0))))
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    layout_id: Option<Uuid>,
+    archived: bool,
+    slug: String,
 }
 
 #[tracing::instrument(skip_all)]
@@ -116,7 +341,7 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-            SELECT title, text_content, html_content
+            SELECT title, text_content, html_content, layout_id, archived, slug
             FROM newsletter_issues
             WHERE
             newsletter_issue_id = $1
@@ -129,37 +354,93 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+struct Recipient {
+    email: String,
+    name: String,
+    status: String,
+    subscription_token: Option<String>,
+}
+
+#[tracing::instrument(skip(executor))]
+async fn get_recipient(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Recipient, anyhow::Error> {
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+            SELECT s.email, s.name, s.status, t.subscription_token AS "subscription_token?"
+            FROM subscriptions s
+            LEFT JOIN subscription_tokens t ON t.subscriber_id = s.id
+            WHERE s.id = $1
+            LIMIT 1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(recipient)
+}
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    email_layout: EmailLayoutSettings,
+    html_sanitizer: HtmlSanitizerSettings,
+) -> Result<(), anyhow::Error> {
+    let worker_id = Uuid::new_v4();
+    let mut last_heartbeat: Option<Instant> = None;
+    let mut attachment_cache = AttachmentCache::default();
     loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
+        if last_heartbeat.is_none_or(|at| at.elapsed() >= HEARTBEAT_INTERVAL) {
+            match record_heartbeat(&pool, worker_id).await {
+                Ok(()) => last_heartbeat = Some(Instant::now()),
+                Err(e) => tracing::error!(error.cause_chain = ?e, error.message = %e),
+            }
+        }
+        // One task from each queue per iteration, so that a large issue does not hold
+        // back the confirmation emails of new subscribers.
+        let confirmation =
+            try_send_confirmation_email(&pool, &email_client, &base_url, &email_layout).await;
+        let delivery = try_execute_task(
+            &pool,
+            &email_client,
+            &base_url,
+            &email_layout,
+            &html_sanitizer,
+            &mut attachment_cache,
+        )
+        .await;
+        match (confirmation, delivery) {
+            (Ok(ExecutionOutcome::EmptyQueue), Ok(ExecutionOutcome::EmptyQueue)) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
-            Err(_) => {
+            (Err(_), _) | (_, Err(_)) => {
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+            _ => {}
         }
     }
 }
 
-enum ExecutionOutcome {
+pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
 }
 
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    let sender_email = configuration
-        .email_client
-        .sender()
-        .expect("Invalid sender email address.");
-    let timeout = configuration.email_client.timeout();
-    let email_client = EmailClient::new(
-        configuration.email_client.base_url.clone().parse()?,
-        sender_email,
-        configuration.email_client.authorization_token.clone(),
-        timeout,
-    );
-    worker_loop(connection_pool, email_client).await
+    let email_client = configuration.email_client.client()?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.email_layout,
+        configuration.html_sanitizer,
+    )
+    .await
 }
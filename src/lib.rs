@@ -3,17 +3,31 @@ use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::{EnvFilter, Registry, fmt::MakeWriter, layer::SubscriberExt};
 
+pub mod attachment;
+pub mod audit;
 pub mod authentication;
 pub mod configuration;
+pub mod confirmation_outbox;
+pub mod delivery_log;
 pub mod domain;
 pub mod email_client;
+pub mod email_layout;
+pub mod form_token;
+pub mod html;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod markdown;
+pub mod metrics;
+pub mod newsletter_issue;
+pub mod rate_limit;
+pub mod request_id;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
+pub mod templating;
 pub mod utils;
-pub mod idempotency;
-pub mod issue_delivery_worker;
 
 pub fn get_subscriber<Sink>(
     name: String,
@@ -25,12 +39,14 @@ where
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let opentelemetry_layer = telemetry::opentelemetry_layer(name.clone());
     let formatting_layer = BunyanFormattingLayer::new(name, sink);
 
     Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(opentelemetry_layer)
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
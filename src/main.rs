@@ -1,4 +1,5 @@
 use tokio::task::JoinError;
+use zero2prod::idempotency::run_sweeper_until_stopped;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
 use zero2prod::{
     configuration::get_configuration, get_subscriber, init_subscriber, startup::Application,
@@ -11,12 +12,15 @@ async fn main() -> anyhow::Result<()> {
     let configuration = get_configuration().expect("Failed to read configuration.");
     let application = Application::build(configuration.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let sweeper_task = tokio::spawn(run_sweeper_until_stopped(configuration));
 
     tokio::select! {
         o = application_task => report_exit("API", o),
         o = worker_task => report_exit("Background worker", o),
+        o = sweeper_task => report_exit("Idempotency sweeper", o),
     };
+    zero2prod::telemetry::shutdown_tracer_provider();
 
     Ok(())
 }
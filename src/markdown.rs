@@ -0,0 +1,171 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+/// Render a Markdown newsletter body into sanitized HTML and a readable plain-text alternative.
+///
+/// Template tags (`{{ ... }}` and `{% ... %}`) are carried over verbatim to both renderings,
+/// so they can still be filled in for every recipient at send time.
+pub fn render(markdown: &str) -> RenderedMarkdown {
+    let (protected, tags) = protect_template_tags(markdown);
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(&protected, options()));
+    let html = ammonia::clean(&html);
+    let text = to_text(&protected);
+
+    RenderedMarkdown {
+        html: restore_template_tags(html, &tags),
+        text: restore_template_tags(text, &tags),
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES
+}
+
+fn placeholder(i: usize) -> String {
+    format!("ZTEMPLATETAG{i}Z")
+}
+
+/// Swap template tags for inert tokens, otherwise Markdown would percent-encode them
+/// in link targets or treat the spaces inside them as the end of a URL.
+fn protect_template_tags(source: &str) -> (String, Vec<&str>) {
+    let mut protected = String::with_capacity(source.len());
+    let mut tags = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find('{') {
+        let close = match &rest[start..] {
+            s if s.starts_with("{{") => "}}",
+            s if s.starts_with("{%") => "%}",
+            _ => {
+                protected.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+                continue;
+            }
+        };
+        let Some(end) = rest[start..].find(close).map(|i| start + i + close.len()) else {
+            break;
+        };
+        protected.push_str(&rest[..start]);
+        protected.push_str(&placeholder(tags.len()));
+        tags.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+
+    (protected, tags)
+}
+
+fn restore_template_tags(mut rendered: String, tags: &[&str]) -> String {
+    for (i, tag) in tags.iter().enumerate() {
+        rendered = rendered.replace(&placeholder(i), tag);
+    }
+    rendered
+}
+
+fn to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // The next number of every open list, `None` for bullet lists.
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(Tag::List(first_number)) => lists.push(first_number),
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        text.push_str(&format!("{n}. "));
+                        *n += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(TagEnd::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => links.push(dest_url.to_string()),
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some(url) = links.pop() {
+                    text.push_str(&format!(" ({url})"));
+                }
+            }
+            Event::End(TagEnd::Paragraph) if !lists.is_empty() => text.push('\n'),
+            Event::End(TagEnd::Paragraph)
+            | Event::End(TagEnd::Heading(_))
+            | Event::End(TagEnd::BlockQuote(_))
+            | Event::End(TagEnd::Table) => text.push_str("\n\n"),
+            Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::TableRow) => text.push('\n'),
+            Event::End(TagEnd::TableHead) => text.push('\n'),
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("----------\n\n"),
+            _ => {}
+        }
+    }
+
+    text.trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let rendered = render("# Title\n\nSome *emphasis*.");
+        assert_eq!(
+            rendered.html,
+            "<h1>Title</h1>\n<p>Some <em>emphasis</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn unsafe_html_is_removed() {
+        let rendered = render("Hello <script>alert('boom')</script><b onclick=\"x()\">there</b>");
+        assert!(!rendered.html.contains("<script"));
+        assert!(!rendered.html.contains("onclick"));
+        assert!(rendered.html.contains("<b>there</b>"));
+    }
+
+    #[test]
+    fn plain_text_keeps_structure_and_links() {
+        let rendered = render(
+            "# Title\n\nRead [the post](https://example.com).\n\n- one\n- two\n\n1. first\n2. second",
+        );
+        assert_eq!(
+            rendered.text,
+            "Title\n\nRead the post (https://example.com).\n\n- one\n- two\n\n1. first\n2. second"
+        );
+    }
+
+    #[test]
+    fn template_tags_survive_in_both_renderings() {
+        let rendered = render("Hi {{ name }}!\n\n[Unsubscribe]({{ unsubscribe_url }})");
+        assert!(rendered.html.contains("<p>Hi {{ name }}!</p>"));
+        assert!(rendered.html.contains("href=\"{{ unsubscribe_url }}\""));
+        assert_eq!(
+            rendered.text,
+            "Hi {{ name }}!\n\nUnsubscribe ({{ unsubscribe_url }})"
+        );
+    }
+
+    #[test]
+    fn unterminated_template_tags_are_left_untouched() {
+        let rendered = render("Hi {{ name");
+        assert_eq!(rendered.text, "Hi {{ name");
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+/// Process-wide metrics. The API and the delivery worker run in the same process
+/// (see `main.rs`), so the worker's counters are exposed by the API's `/metrics` endpoint.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests_total: IntCounterVec,
+    pub http_request_duration_seconds: HistogramVec,
+    pub login_attempts_total: IntCounterVec,
+    pub subscriptions_created_total: IntCounter,
+    pub subscriptions_confirmed_total: IntCounter,
+    pub subscriptions_rejected_total: IntCounterVec,
+    pub issue_delivery_queue_depth: IntGauge,
+    pub confirmation_email_outbox_depth: IntGauge,
+    pub emails_sent_total: IntCounter,
+    pub emails_failed_total: IntCounter,
+    pub issue_emails_sent: IntGaugeVec,
+    pub issue_emails_failed: IntGaugeVec,
+    pub email_client_request_duration_seconds: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let register = |metric: Box<dyn prometheus::core::Collector>| {
+            registry
+                .register(metric)
+                .expect("Failed to register a metric");
+        };
+
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests, by route and status."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests, by route.",
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let login_attempts_total = IntCounterVec::new(
+            Opts::new("login_attempts_total", "Login attempts, by outcome."),
+            &["outcome"],
+        )
+        .unwrap();
+        let subscriptions_created_total = IntCounter::new(
+            "subscriptions_created_total",
+            "Subscriptions created, pending confirmation.",
+        )
+        .unwrap();
+        let subscriptions_confirmed_total =
+            IntCounter::new("subscriptions_confirmed_total", "Subscriptions confirmed.").unwrap();
+        let subscriptions_rejected_total = IntCounterVec::new(
+            Opts::new(
+                "subscriptions_rejected_total",
+                "Subscription form submissions rejected as automated, by reason.",
+            ),
+            &["reason"],
+        )
+        .unwrap();
+        let issue_delivery_queue_depth = IntGauge::new(
+            "issue_delivery_queue_depth",
+            "Newsletter emails waiting to be delivered.",
+        )
+        .unwrap();
+        let confirmation_email_outbox_depth = IntGauge::new(
+            "confirmation_email_outbox_depth",
+            "Confirmation emails waiting to be sent.",
+        )
+        .unwrap();
+        // Not labelled by issue, which would add series forever: the per-issue counts
+        // are the `issue_emails_*` gauges, which only cover recent issues.
+        let emails_sent_total =
+            IntCounter::new("emails_sent_total", "Newsletter emails sent.").unwrap();
+        let emails_failed_total = IntCounter::new(
+            "emails_failed_total",
+            "Newsletter emails that could not be sent.",
+        )
+        .unwrap();
+        let issue_emails_sent = IntGaugeVec::new(
+            Opts::new(
+                "issue_emails_sent",
+                "Emails of the issues being sent or completed in the last day, by issue.",
+            ),
+            &["issue"],
+        )
+        .unwrap();
+        let issue_emails_failed = IntGaugeVec::new(
+            Opts::new(
+                "issue_emails_failed",
+                "Emails of the issues being sent or completed in the last day that could \
+                not be sent, by issue.",
+            ),
+            &["issue"],
+        )
+        .unwrap();
+        let email_client_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "email_client_request_duration_seconds",
+                "Latency of the requests to the email delivery API, by outcome.",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+
+        register(Box::new(http_requests_total.clone()));
+        register(Box::new(http_request_duration_seconds.clone()));
+        register(Box::new(login_attempts_total.clone()));
+        register(Box::new(subscriptions_created_total.clone()));
+        register(Box::new(subscriptions_confirmed_total.clone()));
+        register(Box::new(subscriptions_rejected_total.clone()));
+        register(Box::new(issue_delivery_queue_depth.clone()));
+        register(Box::new(confirmation_email_outbox_depth.clone()));
+        register(Box::new(emails_sent_total.clone()));
+        register(Box::new(emails_failed_total.clone()));
+        register(Box::new(issue_emails_sent.clone()));
+        register(Box::new(issue_emails_failed.clone()));
+        register(Box::new(email_client_request_duration_seconds.clone()));
+
+        Self {
+            registry,
+            http_requests_total,
+            http_request_duration_seconds,
+            login_attempts_total,
+            subscriptions_created_total,
+            subscriptions_confirmed_total,
+            subscriptions_rejected_total,
+            issue_delivery_queue_depth,
+            confirmation_email_outbox_depth,
+            emails_sent_total,
+            emails_failed_total,
+            issue_emails_sent,
+            issue_emails_failed,
+            email_client_request_duration_seconds,
+        }
+    }
+
+    /// All metrics, in the Prometheus text exposition format.
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer).expect("Metrics are always valid UTF-8"))
+    }
+}
+
+/// Count and time every request, labelled by route pattern (e.g. `/admin/newsletters`)
+/// rather than by path, to keep the number of series bounded.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let method = req.method().to_string();
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".into());
+    let start = Instant::now();
+
+    let outcome = next.call(req).await;
+
+    let status = match &outcome {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    METRICS
+        .http_requests_total
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .inc();
+    METRICS
+        .http_request_duration_seconds
+        .with_label_values(&[method.as_str(), route.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    outcome
+}
@@ -0,0 +1,277 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{DeliveryOutcome, IssueStatus};
+use crate::issue_delivery_worker::complete_issue_if_delivered;
+
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub recipient_count: Option<i32>,
+    /// Emails still waiting in the delivery queue.
+    pub queued: i64,
+    pub published_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "List newsletter issues", skip(pool))]
+pub async fn get_issues(pool: &PgPool) -> Result<Vec<IssueSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueSummary,
+        r#"
+            SELECT
+                i.newsletter_issue_id,
+                i.title,
+                i.status,
+                i.recipient_count,
+                (
+                    SELECT count(*)
+                    FROM issue_delivery_queue q
+                    WHERE q.newsletter_issue_id = i.newsletter_issue_id
+                ) AS "queued!",
+                i.published_at,
+                i.completed_at
+            FROM newsletter_issues i
+            ORDER BY i.published_at DESC NULLS FIRST
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// An issue as listed in the public archive.
+pub struct ArchiveEntry {
+    pub title: String,
+    pub slug: String,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+pub struct ArchivedIssue {
+    pub title: String,
+    pub html_content: String,
+    pub layout_id: Option<Uuid>,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+/// Issues published to the archive are public once they go out, unless they were cancelled.
+#[tracing::instrument(name = "List archived newsletter issues", skip(pool))]
+pub async fn get_archive(pool: &PgPool) -> Result<Vec<ArchiveEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchiveEntry,
+        r#"
+            SELECT title, slug, published_at
+            FROM newsletter_issues
+            WHERE archived AND status IN ($1, $2, $3)
+            ORDER BY published_at DESC
+        "#,
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+        IssueStatus::Sent.as_str(),
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get archived newsletter issue", skip(pool))]
+pub async fn get_archived_issue(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+            SELECT title, html_content, layout_id, published_at
+            FROM newsletter_issues
+            WHERE slug = $1 AND archived AND status IN ($2, $3, $4)
+        "#,
+        slug,
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+        IssueStatus::Sent.as_str(),
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// An archived issue, as listed in the feeds.
+pub struct FeedEntry {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub html_content: String,
+    pub published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "List newsletter feed entries", skip(pool))]
+pub async fn get_feed_entries(pool: &PgPool, limit: i64) -> Result<Vec<FeedEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        FeedEntry,
+        r#"
+            SELECT
+                newsletter_issue_id,
+                title,
+                slug,
+                html_content,
+                published_at AS "published_at!"
+            FROM newsletter_issues
+            WHERE archived AND status IN ($1, $2, $3) AND published_at IS NOT NULL
+            ORDER BY published_at DESC
+            LIMIT $4
+        "#,
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+        IssueStatus::Sent.as_str(),
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// What the feeds are built from, which is cheaper to check than the feeds themselves.
+pub struct ArchiveRevision {
+    /// When the archive last changed: an issue was published to it, or cancelled and
+    /// removed. `None` if nothing was ever archived.
+    pub last_modified: Option<DateTime<Utc>>,
+    /// The number of issues in the archive.
+    pub issue_count: i64,
+}
+
+#[tracing::instrument(name = "Get archive revision", skip(pool))]
+pub async fn archive_revision(pool: &PgPool) -> Result<ArchiveRevision, sqlx::Error> {
+    let r = sqlx::query!(
+        r#"
+            SELECT
+                max(
+                    CASE WHEN status = $1 THEN completed_at ELSE published_at END
+                ) AS last_modified,
+                count(*) FILTER (WHERE status <> $1) AS "issue_count!"
+            FROM newsletter_issues
+            WHERE archived AND status IN ($1, $2, $3, $4)
+        "#,
+        IssueStatus::Cancelled.as_str(),
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+        IssueStatus::Sent.as_str(),
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(ArchiveRevision {
+        last_modified: r.last_modified,
+        issue_count: r.issue_count,
+    })
+}
+
+/// Stop the delivery of an issue, the emails being sent when it is paused still go out.
+///
+/// Returns `false` if the issue was not being sent.
+#[tracing::instrument(name = "Pause newsletter issue", skip(transaction))]
+pub async fn pause_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2
+            WHERE newsletter_issue_id = $1 AND status = $3
+        "#,
+        issue_id,
+        IssueStatus::Paused.as_str(),
+        IssueStatus::Sending.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Returns `false` if the issue was not paused.
+#[tracing::instrument(name = "Resume newsletter issue", skip(transaction))]
+pub async fn resume_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2
+            WHERE newsletter_issue_id = $1 AND status = $3
+        "#,
+        issue_id,
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+    // The last emails may have gone out after the issue was paused.
+    complete_issue_if_delivered(transaction, issue_id).await?;
+
+    Ok(n_updated_rows > 0)
+}
+
+/// Drop the emails of an issue still waiting in the queue, recording them as
+/// cancelled in the delivery log.
+///
+/// Returns `false` if the issue was neither being sent nor paused.
+#[tracing::instrument(name = "Cancel newsletter issue", skip(transaction))]
+pub async fn cancel_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    // Queue rows are locked before the issue, as the delivery worker does:
+    // the emails being sent complete first and are not cancelled.
+    sqlx::query!(
+        r#"
+            WITH cancelled AS (
+                DELETE FROM issue_delivery_queue
+                WHERE
+                    newsletter_issue_id = $1 AND
+                    EXISTS (
+                        SELECT 1
+                        FROM newsletter_issues
+                        WHERE newsletter_issue_id = $1 AND status IN ($2, $3)
+                    )
+                RETURNING subscriber_id
+            )
+            INSERT INTO newsletter_deliveries (
+                delivery_id,
+                newsletter_issue_id,
+                subscriber_id,
+                subscriber_email,
+                outcome,
+                attempted_at,
+                completed_at
+            )
+            SELECT gen_random_uuid(), $1, s.id, s.email, $4, now(), now()
+            FROM cancelled c
+            JOIN subscriptions s ON s.id = c.subscriber_id
+        "#,
+        issue_id,
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+        DeliveryOutcome::Cancelled.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+    let n_updated_rows = sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET status = $2, completed_at = now()
+            WHERE newsletter_issue_id = $1 AND status IN ($3, $4)
+        "#,
+        issue_id,
+        IssueStatus::Cancelled.as_str(),
+        IssueStatus::Sending.as_str(),
+        IssueStatus::Paused.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_updated_rows > 0)
+}
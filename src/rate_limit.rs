@@ -0,0 +1,252 @@
+use std::time::Duration;
+
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{StatusCode, header},
+    middleware::Next,
+    web,
+};
+use once_cell::sync::Lazy;
+use redis::{Script, aio::ConnectionManager};
+
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+
+/// Take a token from the bucket at `KEYS[1]`, refilling it first for the time
+/// elapsed since the last call. Running it as a script makes the
+/// read-modify-write atomic across application instances.
+///
+/// `ARGV`: capacity, refill interval (ms).
+/// Returns `{allowed (0 or 1), milliseconds until a token is available}`.
+static TOKEN_BUCKET: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        local capacity = tonumber(ARGV[1])
+        local interval = tonumber(ARGV[2])
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+        local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated_at')
+        local tokens = tonumber(state[1]) or capacity
+        local updated_at = tonumber(state[2]) or now
+        tokens = math.min(capacity, tokens + math.max(0, now - updated_at) / interval)
+
+        local allowed = 0
+        local retry_after = 0
+        if tokens >= 1 then
+            tokens = tokens - 1
+            allowed = 1
+        else
+            retry_after = math.ceil((1 - tokens) * interval)
+        end
+
+        redis.call('HSET', KEYS[1], 'tokens', tokens, 'updated_at', now)
+        redis.call('PEXPIRE', KEYS[1], math.ceil((capacity - tokens) * interval))
+        return {allowed, retry_after}
+        "#,
+    )
+});
+
+/// The buckets of the public endpoints. Each has its own keys, so that a burst of
+/// sign-ups does not prevent earlier subscribers from confirming.
+#[derive(Clone, Copy, Debug)]
+pub enum Bucket {
+    SubscribeByIp,
+    SubscribeByEmail,
+    ConfirmByIp,
+    ResendByEmail,
+    LoginByIp,
+}
+
+impl Bucket {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Bucket::SubscribeByIp => "subscribe:ip",
+            Bucket::SubscribeByEmail => "subscribe:email",
+            Bucket::ConfirmByIp => "confirm:ip",
+            Bucket::ResendByEmail => "resend:email",
+            Bucket::LoginByIp => "login:ip",
+        }
+    }
+
+    fn settings(&self, settings: &RateLimitSettings) -> TokenBucketSettings {
+        match self {
+            Bucket::SubscribeByIp | Bucket::ConfirmByIp | Bucket::LoginByIp => settings.per_ip,
+            Bucket::SubscribeByEmail | Bucket::ResendByEmail => settings.per_email,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimitExceeded {
+    retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Too many requests, please try again later.")
+    }
+}
+
+impl std::error::Error for RateLimitExceeded {}
+
+impl ResponseError for RateLimitExceeded {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Retry-After is in whole seconds, round up so that the retry succeeds.
+        let retry_after = self.retry_after.as_millis().div_ceil(1000).max(1);
+        HttpResponse::build(self.status_code())
+            .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+            .body(self.to_string())
+    }
+}
+
+pub struct RateLimiter {
+    connection: ConnectionManager,
+    settings: RateLimitSettings,
+}
+
+impl RateLimiter {
+    pub fn new(connection: ConnectionManager, settings: RateLimitSettings) -> Self {
+        Self {
+            connection,
+            settings,
+        }
+    }
+
+    /// Take a token from the bucket of the client sending `req`.
+    pub async fn check_client(
+        &self,
+        bucket: Bucket,
+        req: &HttpRequest,
+    ) -> Result<(), RateLimitExceeded> {
+        match ip_address(req, self.settings.trust_forwarded_for) {
+            Some(ip) => self.check(bucket, &ip).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Take a token from the bucket identified by `key`.
+    ///
+    /// Redis being unavailable must not take sign-ups down with it, so requests
+    /// are let through when the bucket cannot be checked.
+    #[tracing::instrument(name = "Check rate limit", skip(self, key))]
+    pub async fn check(&self, bucket: Bucket, key: &str) -> Result<(), RateLimitExceeded> {
+        if !self.settings.enabled {
+            return Ok(());
+        }
+        let TokenBucketSettings {
+            capacity,
+            refill_interval_milliseconds,
+        } = bucket.settings(&self.settings);
+        let redis_key = format!(
+            "{}:{}:{}",
+            self.settings.key_prefix,
+            bucket.as_str(),
+            key.to_lowercase()
+        );
+
+        let outcome: Result<(u8, u64), _> = TOKEN_BUCKET
+            .key(redis_key)
+            .arg(capacity)
+            .arg(refill_interval_milliseconds.max(1))
+            .invoke_async(&mut self.connection.clone())
+            .await;
+        match outcome {
+            Ok((1, _)) => Ok(()),
+            Ok((_, retry_after_ms)) => Err(RateLimitExceeded {
+                retry_after: Duration::from_millis(retry_after_ms),
+            }),
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to check the rate limit, letting the request through."
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The IP address of the client sending `req`.
+///
+/// `Forwarded`/`X-Forwarded-For` are only honoured with `rate_limit.trust_forwarded_for`,
+/// i.e. behind a proxy that sets them: any client can send them otherwise.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trust_forwarded_for = req
+        .app_data::<web::Data<RateLimiter>>()
+        .is_some_and(|rate_limiter| rate_limiter.settings.trust_forwarded_for);
+    ip_address(req, trust_forwarded_for)
+}
+
+fn ip_address(req: &HttpRequest, trust_forwarded_for: bool) -> Option<String> {
+    if trust_forwarded_for {
+        req.connection_info()
+            .realip_remote_addr()
+            .map(ToOwned::to_owned)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+}
+
+/// Apply the per-IP bucket of `/subscribe`, before the form is even parsed.
+pub async fn limit_subscriptions_by_ip(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    limit_by_ip(Bucket::SubscribeByIp, req, next).await
+}
+
+/// Apply the per-IP bucket of `/subscriptions/confirm`.
+pub async fn limit_confirmations_by_ip(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    limit_by_ip(Bucket::ConfirmByIp, req, next).await
+}
+
+/// Apply the per-IP bucket of login attempts, which are open to anyone and each
+/// add an entry to the audit log when they fail.
+pub async fn limit_logins_by_ip(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    limit_by_ip(Bucket::LoginByIp, req, next).await
+}
+
+async fn limit_by_ip(
+    bucket: Bucket,
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if let Some(rate_limiter) = req.app_data::<web::Data<RateLimiter>>() {
+        rate_limiter.check_client(bucket, req.request()).await?;
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix_web::ResponseError;
+
+    use super::RateLimitExceeded;
+
+    #[test]
+    fn retry_after_is_rounded_up_to_the_next_second() {
+        for (millis, expected) in [(0, "1"), (999, "1"), (1000, "1"), (1001, "2")] {
+            let e = RateLimitExceeded {
+                retry_after: Duration::from_millis(millis),
+            };
+            let response = e.error_response();
+            assert_eq!(response.status().as_u16(), 429);
+            assert_eq!(response.headers().get("Retry-After").unwrap(), expected);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use actix_web::{
+    Error, HttpMessage, HttpResponse,
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::{self, Accept, Header, HeaderName, HeaderValue},
+    middleware::Next,
+};
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder, root_span};
+use uuid::Uuid;
+
+use crate::html::escape_text;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Upper bound on the length of a caller-provided request ID.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The ID correlating a request with its logs. It is taken from the `X-Request-Id`
+/// header when the caller provides a well-formed one, otherwise it is generated.
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn from_request(req: &ServiceRequest) -> Self {
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(|id| Self(id.to_owned()))
+            .unwrap_or_else(|| Self(Uuid::new_v4().to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Request IDs end up in logs and response headers, so only a conservative charset is accepted.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Root span builder for `TracingLogger` that records our `RequestId` instead of
+/// the one generated by `tracing-actix-web`.
+pub struct RequestIdRootSpanBuilder;
+
+impl RootSpanBuilder for RequestIdRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let request_id = RequestId::from_request(request);
+        let span = root_span!(request);
+        span.record("request_id", request_id.as_str());
+        request.extensions_mut().insert(request_id);
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+/// Echo the request ID in the response and render errors as an HTML page or a
+/// problem details document (RFC 9457), depending on what the client accepts.
+///
+/// It must be wrapped inside `TracingLogger<RequestIdRootSpanBuilder>`, which assigns the ID.
+pub async fn request_id_middleware(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(request_id) = req.extensions().get::<RequestId>().cloned() else {
+        return next.call(req).await;
+    };
+    let json = prefers_json(&req);
+
+    match next.call(req).await {
+        Ok(response) => {
+            let (request, response) = response.into_parts();
+            let response = finalize_response(response, &request_id, json);
+            Ok(ServiceResponse::new(request, response))
+        }
+        // Errors bubbling up from other middlewares carry their own response.
+        Err(e) => {
+            let response = finalize_response(e.error_response(), &request_id, json);
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// Error responses are rendered whether they come from an error or were built directly,
+/// e.g. `HttpResponse::NotFound().finish()`, unless the handler provided a body.
+fn finalize_response(response: HttpResponse, request_id: &RequestId, json: bool) -> HttpResponse {
+    let status = response.status();
+    let mut response = if status.is_client_error() || status.is_server_error() {
+        let is_empty = matches!(response.body().size(), BodySize::None | BodySize::Sized(0));
+        match response.error().map(|e| e.to_string()) {
+            Some(detail) => render_error(response, Some(&detail), request_id, json),
+            None if is_empty => render_error(response, None, request_id, json),
+            None => response,
+        }
+    } else {
+        response
+    };
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn render_error(
+    response: HttpResponse,
+    detail: Option<&str>,
+    request_id: &RequestId,
+    json: bool,
+) -> HttpResponse {
+    let status = response.status();
+    // Server errors stay opaque: the details are in the logs, under the request ID.
+    let detail = detail.filter(|_| status.is_client_error());
+    let title = status.canonical_reason().unwrap_or("Error");
+
+    let (content_type, body) = if json {
+        let mut problem = serde_json::json!({
+            "type": "about:blank",
+            "title": title,
+            "status": status.as_u16(),
+            "request_id": request_id.as_str(),
+        });
+        if let Some(detail) = detail {
+            problem["detail"] = detail.into();
+        }
+        ("application/problem+json", problem.to_string())
+    } else {
+        let detail_html = detail
+            .map(|d| format!("<p>{}</p>\n", escape_text(d)))
+            .unwrap_or_default();
+        let html = format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{} {title}</h1>
+    {detail_html}
+    <p>Request ID: <code>{}</code></p>
+</body>
+</html>"#,
+            status.as_u16(),
+            escape_text(request_id.as_str()),
+        );
+        ("text/html; charset=utf-8", html)
+    };
+
+    let mut response = response.set_body(BoxBody::new(body));
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+/// Whether the client ranks a JSON media type above HTML. Browsers, and clients
+/// without an `Accept` header, get HTML.
+fn prefers_json(req: &ServiceRequest) -> bool {
+    let Ok(accept) = Accept::parse(req) else {
+        return false;
+    };
+    accept
+        .ranked()
+        .iter()
+        .find_map(
+            |mime| match (mime.type_().as_str(), mime.subtype().as_str()) {
+                ("text", "html") => Some(false),
+                ("application", "json") => Some(true),
+                ("application", "problem") if mime.suffix().is_some_and(|s| s == "json") => {
+                    Some(true)
+                }
+                _ => None,
+            },
+        )
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_request_id;
+
+    #[test]
+    fn well_formed_request_ids_are_accepted() {
+        assert!(is_valid_request_id("3f2a9c1e-6b1d-4f4e-9a63-2a1f2c0b7d55"));
+        assert!(is_valid_request_id("req_42.retry-1"));
+    }
+
+    #[test]
+    fn malformed_request_ids_are_rejected() {
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("a b"));
+        assert!(!is_valid_request_id("<script>"));
+        assert!(!is_valid_request_id(&"a".repeat(129)));
+    }
+}
@@ -0,0 +1,96 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::{
+    attachment::{display_size, get_attachments},
+    configuration::AttachmentSettings,
+    html::{escape_attribute, escape_text, flash_messages_html},
+    utils::e500,
+};
+
+pub async fn attachments(
+    pool: web::Data<PgPool>,
+    settings: web::Data<AttachmentSettings>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+
+    let mut rows_html = String::new();
+    for a in get_attachments(&pool).await.map_err(e500)? {
+        let inline_html = match &a.content_id {
+            Some(content_id) => format!(
+                "<code>&lt;img src=\"cid:{}\"&gt;</code>",
+                escape_text(content_id)
+            ),
+            None => String::new(),
+        };
+        rows_html.push_str(&format!(
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{inline_html}</td>
+                <td>{}</td>
+                <td>
+                    <form action="/admin/attachments/delete" method="post">
+                        <input type="hidden" name="attachment_id" value="{}">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>
+            "#,
+            escape_text(&a.file_name),
+            escape_text(&a.content_type),
+            display_size(a.size.try_into().unwrap_or_default()),
+            a.created_at.format("%Y-%m-%d %H:%M UTC"),
+            escape_attribute(a.attachment_id.to_string()),
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Attachments</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Name</th>
+                        <th>Type</th>
+                        <th>Size</th>
+                        <th>Inline reference</th>
+                        <th>Added</th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p>
+                    Uploaded files can be sent with a newsletter issue from the publish form.
+                    Inline images are shown where the HTML content references them.
+                    Files may not exceed {}, and the files sent with an issue {} altogether.
+                </p>
+                <form action="/admin/attachments" method="post" enctype="multipart/form-data">
+                    <label>File
+                        <input type="file" name="file">
+                    </label>
+                    <br>
+                    <label>
+                        <input type="checkbox" name="inline" value="on"> Inline image
+                    </label>
+                    <br>
+                    <button type="submit">Upload</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+            display_size(settings.max_file_bytes),
+            display_size(settings.max_total_bytes),
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::attachments;
+pub use post::{delete_uploaded_attachment, upload_attachment, upload_config};
@@ -0,0 +1,164 @@
+use actix_multipart::{
+    MultipartError,
+    form::{MultipartForm, MultipartFormConfig, bytes::Bytes, text::Text},
+};
+use actix_web::{HttpResponse, error::InternalError, error::PayloadError, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    attachment::{delete_attachment, display_size, insert_attachment},
+    audit::{self, AuditAction, ClientInfo},
+    authentication::UserId,
+    configuration::AttachmentSettings,
+    utils::{e500, see_other},
+};
+
+/// Room left for the other fields of the upload form.
+const FORM_OVERHEAD_BYTES: usize = 16_384;
+
+/// Uploads are cut short once they are larger than a file may be.
+pub fn upload_config(settings: &AttachmentSettings) -> MultipartFormConfig {
+    let max_file_bytes = settings.max_file_bytes;
+    let limit = max_file_bytes + FORM_OVERHEAD_BYTES;
+    MultipartFormConfig::default()
+        .total_limit(limit)
+        .memory_limit(limit)
+        .error_handler(move |e, _| match e {
+            MultipartError::Payload(PayloadError::Overflow) => {
+                FlashMessage::error(too_large(max_file_bytes)).send();
+                InternalError::from_response(e, see_other("/admin/attachments")).into()
+            }
+            e => e.into(),
+        })
+}
+
+fn too_large(max_file_bytes: usize) -> String {
+    format!(
+        "The file is too large, attachments may not exceed {}.",
+        display_size(max_file_bytes)
+    )
+}
+
+#[derive(MultipartForm)]
+pub struct UploadFormData {
+    file: Bytes,
+    /// Set to show an image in the HTML body rather than as an attachment.
+    inline: Option<Text<String>>,
+}
+
+#[tracing::instrument(name = "Upload an attachment", skip_all)]
+pub async fn upload_attachment(
+    MultipartForm(form): MultipartForm<UploadFormData>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<AttachmentSettings>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let file_name = form.file.file_name.as_deref().unwrap_or_default().trim();
+    if file_name.is_empty() || form.file.data.is_empty() {
+        FlashMessage::error("Pick a non-empty file to upload.").send();
+        return Ok(see_other("/admin/attachments"));
+    }
+    if form.file.data.len() > settings.max_file_bytes {
+        FlashMessage::error(too_large(settings.max_file_bytes)).send();
+        return Ok(see_other("/admin/attachments"));
+    }
+    let content_type = form
+        .file
+        .content_type
+        .as_ref()
+        .map_or("application/octet-stream", |mime| mime.essence_str());
+    let inline = form.inline.is_some();
+    if inline && !content_type.starts_with("image/") {
+        FlashMessage::error("Only images can be shown inline.").send();
+        return Ok(see_other("/admin/attachments"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let attachment_id = insert_attachment(
+        &mut *transaction,
+        file_name,
+        content_type,
+        &form.file.data,
+        inline,
+    )
+    .await
+    .map_err(e500)?;
+    audit::record(
+        &mut *transaction,
+        Some(**user_id),
+        AuditAction::AttachmentUploaded,
+        Some(&attachment_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store an attachment.")
+        .map_err(e500)?;
+    FlashMessage::info("The attachment has been uploaded.").send();
+
+    Ok(see_other("/admin/attachments"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteFormData {
+    attachment_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Delete an attachment",
+    skip_all,
+    fields(attachment_id = %form.attachment_id)
+)]
+pub async fn delete_uploaded_attachment(
+    form: web::Form<DeleteFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let deleted = delete_attachment(&mut *transaction, form.attachment_id)
+        .await
+        .map_err(e500)?;
+    if deleted {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::AttachmentDeleted,
+            Some(&form.attachment_id.to_string()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete an attachment.")
+        .map_err(e500)?;
+
+    if deleted {
+        FlashMessage::info("The attachment has been deleted.").send();
+    } else {
+        FlashMessage::error(
+            "The attachment was not found, or it was sent with a newsletter issue and is kept.",
+        )
+        .send();
+    }
+
+    Ok(see_other("/admin/attachments"))
+}
@@ -0,0 +1,209 @@
+use actix_web::{
+    HttpRequest, HttpResponse,
+    http::header::{self, ContentType},
+    web,
+};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{AuditAction, AuditEntry, AuditFilter, get_entries},
+    html::{escape_attribute, escape_text},
+    utils::{e400, e500},
+};
+
+/// Entries shown on the page; the CSV export is not limited.
+const PAGE_LIMIT: i64 = 200;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    username: Option<String>,
+    action: Option<String>,
+    /// First day to include, as `YYYY-MM-DD`.
+    since: Option<String>,
+    /// Last day to include, as `YYYY-MM-DD`.
+    until: Option<String>,
+}
+
+impl QueryParams {
+    fn filter(self) -> Result<AuditFilter, String> {
+        let non_empty =
+            |s: Option<String>| s.map(|s| s.trim().to_owned()).filter(|s| !s.is_empty());
+        Ok(AuditFilter {
+            username: non_empty(self.username),
+            action: non_empty(self.action)
+                .map(AuditAction::try_from)
+                .transpose()?,
+            since: non_empty(self.since)
+                .map(|d| start_of_day(&d, 0))
+                .transpose()?,
+            until: non_empty(self.until)
+                .map(|d| start_of_day(&d, 1))
+                .transpose()?,
+        })
+    }
+}
+
+fn start_of_day(date: &str, days_after: u64) -> Result<DateTime<Utc>, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.checked_add_days(Days::new(days_after)))
+        .map(|d| d.and_time(Default::default()).and_utc())
+        .ok_or_else(|| format!("{date} is not a valid date (YYYY-MM-DD)."))
+}
+
+pub async fn audit_log(
+    req: HttpRequest,
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = query.username.clone().unwrap_or_default();
+    let since = query.since.clone().unwrap_or_default();
+    let until = query.until.clone().unwrap_or_default();
+    let filter = query.0.filter().map_err(e400)?;
+    let entries = get_entries(&pool, &filter, Some(PAGE_LIMIT))
+        .await
+        .map_err(e500)?;
+
+    let mut action_options = String::from(r#"<option value="">Any action</option>"#);
+    for action in AuditAction::ALL {
+        action_options.push_str(&format!(
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            action.as_str(),
+            if filter.action == Some(action) {
+                " selected"
+            } else {
+                ""
+            },
+        ));
+    }
+
+    let mut rows_html = String::new();
+    for e in &entries {
+        rows_html.push_str(&format!(
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+            </tr>
+            "#,
+            e.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            escape_text(e.username.as_deref().unwrap_or("-")),
+            e.action,
+            escape_text(e.target.as_deref().unwrap_or_default()),
+            escape_text(e.ip_address.as_deref().unwrap_or_default()),
+            escape_text(e.user_agent.as_deref().unwrap_or_default()),
+        ));
+    }
+    let export_url = format!("/admin/audit/export?{}", req.query_string());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Audit log</title>
+            </head>
+            <body>
+                <form action="/admin/audit" method="get">
+                    <input type="text" name="username" placeholder="Username" value="{}">
+                    <select name="action">{action_options}</select>
+                    <label>From <input type="date" name="since" value="{}"></label>
+                    <label>To <input type="date" name="until" value="{}"></label>
+                    <button type="submit">Filter</button>
+                </form>
+                <p><a href="{}">Export as CSV</a></p>
+                <table>
+                    <tr>
+                        <th>When</th>
+                        <th>User</th>
+                        <th>Action</th>
+                        <th>Target</th>
+                        <th>IP address</th>
+                        <th>User agent</th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p>Showing the {PAGE_LIMIT} most recent matching entries at most.</p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+            escape_attribute(&username),
+            escape_attribute(&since),
+            escape_attribute(&until),
+            escape_attribute(&export_url),
+        )))
+}
+
+pub async fn export_audit_log(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter = query.0.filter().map_err(e400)?;
+    let entries = get_entries(&pool, &filter, None).await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            r#"attachment; filename="audit-log.csv""#,
+        ))
+        .body(to_csv(&entries)))
+}
+
+fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut csv = String::from("created_at,username,action,target,ip_address,user_agent\r\n");
+    for e in entries {
+        let fields = [
+            &e.created_at.to_rfc3339(),
+            e.username.as_deref().unwrap_or_default(),
+            &e.action,
+            e.target.as_deref().unwrap_or_default(),
+            e.ip_address.as_deref().unwrap_or_default(),
+            e.user_agent.as_deref().unwrap_or_default(),
+        ];
+        let row: Vec<_> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// Quote a CSV field (RFC 4180). Values that a spreadsheet would evaluate as a
+/// formula are prefixed with a `'`, since targets and user agents are user input.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value.to_owned()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn formulas_are_neutralized() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+    }
+}
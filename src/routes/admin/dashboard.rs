@@ -7,7 +7,7 @@ use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::session_state::TypedSession;
+use crate::{html::escape_text, session_state::TypedSession};
 
 // Return an opaque 500 while preserving the error's root cause for logging.
 fn e500<T>(e: T) -> actix_web::Error
@@ -39,9 +39,14 @@ pub async fn admin_dashboard(
                         <title>Admin dashboard</title>
                     </head>
                     <body>
-                        <p>Welcome {username}!</p>
+                        <p>Welcome {}!</p>
                         <ol>
                             <li><a href="/admin/password">Change password</a></li>
+                            <li><a href="/admin/issues">Newsletter issues</a></li>
+                            <li><a href="/admin/suppressions">Suppression list</a></li>
+                            <li><a href="/admin/layouts">Email layouts</a></li>
+                            <li><a href="/admin/attachments">Attachments</a></li>
+                            <li><a href="/admin/audit">Audit log</a></li>
                             <li>
                                 <form name="logoutForm" action="/admin/logout" method="post">
                                     <input type="submit" value="Logout">
@@ -51,6 +56,7 @@ pub async fn admin_dashboard(
                     </body>
                 </html>
             "#,
+            escape_text(&username)
         )))
 }
 
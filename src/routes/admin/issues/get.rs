@@ -0,0 +1,92 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::{
+    domain::IssueStatus,
+    html::{escape_attribute, escape_text, flash_messages_html},
+    newsletter_issue::get_issues,
+    utils::e500,
+};
+
+pub async fn newsletter_issues(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+
+    let mut rows_html = String::new();
+    for i in get_issues(&pool).await.map_err(e500)? {
+        let issue_id = escape_attribute(i.newsletter_issue_id.to_string());
+        let mut actions_html = String::new();
+        let actions: &[(&str, &str)] = match IssueStatus::try_from(i.status.clone()) {
+            Ok(IssueStatus::Sending) => &[("pause", "Pause"), ("cancel", "Cancel")],
+            Ok(IssueStatus::Paused) => &[("resume", "Resume"), ("cancel", "Cancel")],
+            _ => &[],
+        };
+        for (action, label) in actions {
+            actions_html.push_str(&format!(
+                r#"<form action="/admin/issues/{action}" method="post">
+                        <input type="hidden" name="newsletter_issue_id" value="{issue_id}">
+                        <button type="submit">{label}</button>
+                    </form>
+                    "#,
+            ));
+        }
+        rows_html.push_str(&format!(
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{actions_html}</td>
+            </tr>
+            "#,
+            escape_text(&i.title),
+            i.status,
+            i.recipient_count.map(|n| n.to_string()).unwrap_or_default(),
+            i.queued,
+            i.published_at
+                .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default(),
+            i.completed_at
+                .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default(),
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Newsletter issues</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Title</th>
+                        <th>Status</th>
+                        <th>Recipients</th>
+                        <th>Queued</th>
+                        <th>Published</th>
+                        <th>Completed</th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p>
+                    Pausing an issue holds back its queued emails until it is resumed,
+                    cancelling it drops them. Emails already being sent still go out.
+                </p>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::newsletter_issues;
+pub use post::{cancel_newsletter_issue, pause_newsletter_issue, resume_newsletter_issue};
@@ -0,0 +1,156 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditAction, ClientInfo},
+    authentication::UserId,
+    newsletter_issue::{cancel_issue, pause_issue, resume_issue},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Pause a newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn pause_newsletter_issue(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = form.newsletter_issue_id;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let paused = pause_issue(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    if paused {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::NewsletterPaused,
+            Some(&issue_id.to_string()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to pause a newsletter issue.")
+        .map_err(e500)?;
+
+    if paused {
+        FlashMessage::info("The issue has been paused.").send();
+    } else {
+        FlashMessage::error("Only issues being sent can be paused.").send();
+    }
+
+    Ok(see_other("/admin/issues"))
+}
+
+#[tracing::instrument(
+    name = "Resume a newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn resume_newsletter_issue(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = form.newsletter_issue_id;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let resumed = resume_issue(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    if resumed {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::NewsletterResumed,
+            Some(&issue_id.to_string()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to resume a newsletter issue.")
+        .map_err(e500)?;
+
+    if resumed {
+        FlashMessage::info("The issue has been resumed.").send();
+    } else {
+        FlashMessage::error("Only paused issues can be resumed.").send();
+    }
+
+    Ok(see_other("/admin/issues"))
+}
+
+#[tracing::instrument(
+    name = "Cancel a newsletter issue",
+    skip_all,
+    fields(newsletter_issue_id = %form.newsletter_issue_id)
+)]
+pub async fn cancel_newsletter_issue(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = form.newsletter_issue_id;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let cancelled = cancel_issue(&mut transaction, issue_id)
+        .await
+        .map_err(e500)?;
+    if cancelled {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::NewsletterCancelled,
+            Some(&issue_id.to_string()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a newsletter issue.")
+        .map_err(e500)?;
+
+    if cancelled {
+        FlashMessage::info("The issue has been cancelled, its queued emails will not be sent.")
+            .send();
+    } else {
+        FlashMessage::error("Only issues being sent or paused can be cancelled.").send();
+    }
+
+    Ok(see_other("/admin/issues"))
+}
@@ -0,0 +1,112 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::EmailLayoutSettings,
+    email_layout::{BUILT_IN_HTML_TEMPLATE, BUILT_IN_TEXT_TEMPLATE, get_layouts},
+    html::{escape_attribute, escape_text, flash_messages_html},
+    utils::e500,
+};
+
+pub async fn email_layouts(
+    pool: web::Data<PgPool>,
+    settings: web::Data<EmailLayoutSettings>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+
+    let layouts = get_layouts(&pool).await.map_err(e500)?;
+    let default_layout = settings
+        .default_layout
+        .as_deref()
+        .filter(|name| layouts.iter().any(|l| l.name == *name));
+    let default_html = match default_layout {
+        Some(name) => format!(
+            "<p>Emails use the <b>{}</b> layout unless an issue picks another one.</p>",
+            escape_text(name)
+        ),
+        None => "<p>Emails use the built-in layout unless an issue picks another one.</p>".into(),
+    };
+
+    let mut rows_html = String::new();
+    for l in &layouts {
+        rows_html.push_str(&format!(
+            r#"<tr>
+                <td>{}{}</td>
+                <td><pre>{}</pre></td>
+                <td><pre>{}</pre></td>
+                <td>{}</td>
+                <td>
+                    <form action="/admin/layouts/delete" method="post">
+                        <input type="hidden" name="layout_id" value="{}">
+                        <button type="submit">Delete</button>
+                    </form>
+                </td>
+            </tr>
+            "#,
+            escape_text(&l.name),
+            if Some(l.name.as_str()) == default_layout {
+                " (default)"
+            } else {
+                ""
+            },
+            escape_text(&l.html_template),
+            escape_text(&l.text_template),
+            l.created_at.format("%Y-%m-%d %H:%M UTC"),
+            escape_attribute(l.layout_id.to_string()),
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Email layouts</title>
+            </head>
+            <body>
+                {msg_html}
+                {default_html}
+                <table>
+                    <tr>
+                        <th>Name</th>
+                        <th>HTML</th>
+                        <th>Text</th>
+                        <th>Added</th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p>
+                    Layouts wrap the body of every outgoing email, which is inserted with
+                    <code>{{{{ content }}}}</code>. They can also use <code>{{{{ physical_address }}}}</code>
+                    and, for newsletter issues, <code>{{{{ name }}}}</code>, <code>{{{{ unsubscribe_url }}}}</code>
+                    and <code>{{{{ preferences_url }}}}</code> - check them with
+                    <code>{{% if unsubscribe_url %}}</code>, they are not set for confirmation emails.
+                </p>
+                <form action="/admin/layouts" method="post">
+                    <label>Name
+                        <input type="text" name="name" placeholder="Layout name">
+                    </label>
+                    <br>
+                    <label>HTML template
+                        <textarea name="html_template">{}</textarea>
+                    </label>
+                    <br>
+                    <label>Text template
+                        <textarea name="text_template">{}</textarea>
+                    </label>
+                    <br>
+                    <button type="submit">Add layout</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+            escape_text(BUILT_IN_HTML_TEMPLATE),
+            escape_text(BUILT_IN_TEXT_TEMPLATE),
+        )))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::email_layouts;
+pub use post::{create_email_layout, delete_email_layout};
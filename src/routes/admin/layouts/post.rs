@@ -0,0 +1,136 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditAction, ClientInfo},
+    authentication::UserId,
+    email_layout::{delete_layout, insert_layout, layout_in_use},
+    templating::{ContentKind, validate_layout},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct CreateFormData {
+    name: String,
+    html_template: String,
+    text_template: String,
+}
+
+#[tracing::instrument(name = "Create an email layout", skip_all, fields(name = %form.name))]
+pub async fn create_email_layout(
+    form: web::Form<CreateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The layout must have a name.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    if let Err(e) = validate_layout(&form.html_template, ContentKind::Html) {
+        FlashMessage::error(format!("The HTML template is not valid: {e}")).send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    if let Err(e) = validate_layout(&form.text_template, ContentKind::Text) {
+        FlashMessage::error(format!("The text template is not valid: {e}")).send();
+        return Ok(see_other("/admin/layouts"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let inserted = insert_layout(
+        &mut *transaction,
+        name,
+        &form.html_template,
+        &form.text_template,
+    )
+    .await
+    .map_err(e500)?;
+    if inserted {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::LayoutCreated,
+            Some(name),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to add a layout.")
+        .map_err(e500)?;
+
+    if inserted {
+        FlashMessage::info(format!("The {name} layout has been added.")).send();
+    } else {
+        FlashMessage::error(format!("A layout named {name} already exists.")).send();
+    }
+
+    Ok(see_other("/admin/layouts"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteFormData {
+    layout_id: Uuid,
+}
+
+#[tracing::instrument(name = "Delete an email layout", skip_all)]
+pub async fn delete_email_layout(
+    form: web::Form<DeleteFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    if layout_in_use(&mut *transaction, form.layout_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(
+            "The layout is used by issues that are being sent or are in the archive.",
+        )
+        .send();
+        return Ok(see_other("/admin/layouts"));
+    }
+    let deleted = delete_layout(&mut *transaction, form.layout_id)
+        .await
+        .map_err(e500)?;
+    if deleted {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::LayoutDeleted,
+            Some(&form.layout_id.to_string()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a layout.")
+        .map_err(e500)?;
+
+    if deleted {
+        FlashMessage::info("The layout has been deleted.").send();
+    } else {
+        FlashMessage::error("The layout was not found.").send();
+    }
+
+    Ok(see_other("/admin/layouts"))
+}
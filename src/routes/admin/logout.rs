@@ -1,17 +1,27 @@
-use actix_web::HttpResponse;
+use actix_web::{HttpResponse, web};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
 use crate::{
+    audit::{self, AuditAction, ClientInfo},
     session_state::TypedSession,
     utils::{e500, see_other},
 };
 
-pub async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
-    if session.get_user_id().map_err(e500)?.is_none() {
-        Ok(see_other("/login"))
-    } else {
+pub async fn log_out(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(user_id) = session.get_user_id().map_err(e500)? {
+        // Recorded first: if it fails, the session is left as it is.
+        audit::record(&**pool, Some(user_id), AuditAction::Logout, None, &client)
+            .await
+            .map_err(e500)?;
         session.log_out();
         FlashMessage::info("You have successfully logged out.").send();
         Ok(see_other("/login"))
+    } else {
+        Ok(see_other("/login"))
     }
 }
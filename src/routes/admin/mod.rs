@@ -1,9 +1,19 @@
+mod attachments;
+mod audit;
 mod dashboard;
+mod issues;
+mod layouts;
 mod logout;
-mod password;
 mod newsletter;
+mod password;
+mod suppressions;
 
+pub use attachments::*;
+pub use audit::*;
 pub use dashboard::admin_dashboard;
+pub use issues::*;
+pub use layouts::*;
 pub use logout::log_out;
-pub use password::*;
 pub use newsletter::*;
+pub use password::*;
+pub use suppressions::*;
@@ -1,24 +1,39 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpResponse, http::header::ContentType, web};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use uuid::Uuid;
 
+use super::post::FormData;
 use crate::{
+    attachment::{Attachment, get_attachments},
+    email_layout::{EmailLayout, get_layouts},
+    html::{escape_attribute, escape_text, flash_messages_html},
     session_state::TypedSession,
     utils::{e500, see_other},
 };
 
 pub async fn publish_newsletter_form(
     session: TypedSession,
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     }
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        msg_html.push_str(&format!("<p><i>{}</i></p>\n", m.content()));
-    }
-    let key = Uuid::new_v4();
+    let msg_html = flash_messages_html(&flash_messages);
+    let layouts = get_layouts(&pool).await.map_err(e500)?;
+    let form = FormData {
+        title: String::new(),
+        text_content: None,
+        html_content: None,
+        markdown_content: None,
+        layout_id: None,
+        attachment_ids: Vec::new(),
+        publish_to_archive: None,
+        idempotency_key: Some(Uuid::new_v4().to_string()),
+    };
+    let attachments = get_attachments(&pool).await.map_err(e500)?;
+    let form_html = publish_form_html(&form, &layouts, &attachments);
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -30,25 +45,109 @@ pub async fn publish_newsletter_form(
             </head>
             <body>
                 {msg_html}
-                <form action="/admin/newsletter" method="post">
+                {form_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
+
+/// The publish form, pre-filled with the given values (e.g. when coming back from a preview).
+pub(super) fn publish_form_html(
+    form: &FormData,
+    layouts: &[EmailLayout],
+    attachments: &[Attachment],
+) -> String {
+    let mut layout_options = String::new();
+    for l in layouts {
+        let layout_id = l.layout_id.to_string();
+        layout_options.push_str(&format!(
+            r#"<option value="{}"{}>{}</option>"#,
+            escape_attribute(&layout_id),
+            if form.layout_id.as_deref() == Some(layout_id.as_str()) {
+                " selected"
+            } else {
+                ""
+            },
+            escape_text(&l.name),
+        ));
+    }
+    let mut attachment_checkboxes = String::new();
+    for a in attachments {
+        attachment_checkboxes.push_str(&format!(
+            r#"<label><input type="checkbox" name="attachment_id" value="{}"{}> {}{}</label><br>"#,
+            escape_attribute(a.attachment_id.to_string()),
+            if form.attachment_ids.contains(&a.attachment_id) {
+                " checked"
+            } else {
+                ""
+            },
+            escape_text(&a.file_name),
+            match &a.content_id {
+                Some(content_id) =>
+                    format!(" (inline, <code>cid:{}</code>)", escape_text(content_id)),
+                None => String::new(),
+            },
+        ));
+    }
+    format!(
+        r#"
+                <p>
+                    All contents can use the <code>{{{{ name }}}}</code>,
+                    <code>{{{{ unsubscribe_url }}}}</code> and <code>{{{{ preferences_url }}}}</code>
+                    placeholders, which are filled in for every recipient.
+                    Issues published to the archive also have a <code>{{{{ view_in_browser_url }}}}</code>.
+                </p>
+                <form action="/admin/newsletters" method="post">
                     <label>Title
-                        <input type="text" name="title" placeholder="Newsletter title">
+                        <input type="text" name="title" placeholder="Newsletter title" value="{}">
                     </label>
                     <br>
+                    <label>Markdown content
+                        <textarea name="markdown_content" placeholder="Markdown version of the newsletter - the text and HTML versions are generated from it">{}</textarea>
+                    </label>
+                    <br>
+                    <p>Or, without Markdown content:</p>
                     <label>Text content
-                        <textarea name="text_content" placeholder="Text version of the newsletter"></textarea>
+                        <textarea name="text_content" placeholder="Text version of the newsletter">{}</textarea>
                     </label>
                     <br>
                     <label>HTML content
-                        <textarea name="html_content" placeholder="HTML version of the newsletter"></textarea>
+                        <textarea name="html_content" placeholder="HTML version of the newsletter">{}</textarea>
+                    </label>
+                    <br>
+                    <label>Layout
+                        <select name="layout_id">
+                            <option value="">Default layout</option>
+                            {layout_options}
+                        </select>
+                    </label>
+                    <br>
+                    <label>
+                        <input type="checkbox" name="publish_to_archive" value="on"{}> Publish to the public archive
                     </label>
                     <br>
-                    <input type="hidden" name="idempotency_key" value="{key}">
+                    <fieldset>
+                        <legend>Attachments</legend>
+                        {attachment_checkboxes}
+                        <a href="/admin/attachments">Upload files</a>
+                    </fieldset>
+                    <input type="hidden" name="idempotency_key" value="{}">
+                    <button type="submit" formaction="/admin/newsletters/preview">Preview</button>
                     <button type="submit">Send newsletter</button>
                 </form>
-                <p><a href="/admin/dashboard">&lt;- Back</a></p>
-            </body>
-        </html>
         "#,
-        )))
+        escape_attribute(&form.title),
+        escape_text(form.markdown_content.as_deref().unwrap_or_default()),
+        escape_text(form.text_content.as_deref().unwrap_or_default()),
+        escape_text(form.html_content.as_deref().unwrap_or_default()),
+        if form.publish_to_archive.is_some() {
+            " checked"
+        } else {
+            ""
+        },
+        // E.g. when previewing a request keyed by header, the form needs a key of its own.
+        escape_attribute(form.idempotency_key.clone().unwrap_or_else(|| Uuid::new_v4().to_string())),
+    )
 }
@@ -1,5 +1,7 @@
 mod get;
 mod post;
+mod preview;
 
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use post::{confirm_replayed_publication, publish_newsletter};
+pub use preview::preview_newsletter;
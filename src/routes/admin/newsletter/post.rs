@@ -1,67 +1,220 @@
-use actix_web::{HttpResponse, web};
+use actix_web::{
+    HttpResponse,
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web,
+};
 use actix_web_flash_messages::FlashMessage;
+use actix_web_lab::extract::UrlEncodedForm;
 use anyhow::Context;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    attachment::{attach_to_issue, display_size, total_size},
+    audit::{self, AuditAction, ClientInfo},
     authentication::UserId,
-    idempotency::{IdempotencyKey, NextAction, save_response, try_processing},
-    utils::{e400, e500, see_other},
+    configuration::{AttachmentSettings, EmailLayoutSettings},
+    domain::{IssueContent, IssueStatus, NewsletterIssue},
+    email_layout::{layout_exists, resolve_layout},
+    idempotency::{IDEMPOTENT_REPLAYED_HEADER, IdempotencyClaim, not_replayable},
+    issue_delivery_worker::complete_issue_if_delivered,
+    markdown,
+    telemetry::TraceContext,
+    utils::{e500, see_other},
 };
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
-    title: String,
-    text_content: String,
-    html_content: String,
-    idempotency_key: String,
+    #[serde(default)]
+    pub(super) title: String,
+    pub(super) text_content: Option<String>,
+    pub(super) html_content: Option<String>,
+    pub(super) markdown_content: Option<String>,
+    /// Overrides the default email layout, if not empty.
+    pub(super) layout_id: Option<String>,
+    /// The uploaded files to send with the issue, one field per file.
+    #[serde(default, rename = "attachment_id")]
+    pub(super) attachment_ids: Vec<Uuid>,
+    /// Set by the checkbox that lists the issue in the public archive.
+    pub(super) publish_to_archive: Option<String>,
+    /// Read by [`enforce_idempotency`](crate::idempotency::enforce_idempotency),
+    /// which also accepts the key in an `Idempotency-Key` header.
+    pub(super) idempotency_key: Option<String>,
+}
+
+impl FormData {
+    /// A non-empty Markdown body takes precedence over the text and HTML fields,
+    /// both of which are then generated from it.
+    pub(super) fn content(&self) -> Result<IssueContent, String> {
+        match (
+            self.markdown_content.as_deref().map(str::trim),
+            &self.text_content,
+            &self.html_content,
+        ) {
+            (Some(markdown), _, _) if !markdown.is_empty() => {
+                let rendered = markdown::render(markdown);
+                Ok(IssueContent {
+                    text: rendered.text,
+                    html: rendered.html,
+                    markdown: Some(markdown.to_owned()),
+                })
+            }
+            (_, Some(text), Some(html)) => Ok(IssueContent {
+                text: text.clone(),
+                html: html.clone(),
+                markdown: None,
+            }),
+            _ => Err("The newsletter must have either Markdown content \
+                or both text and HTML content."
+                .into()),
+        }
+    }
+
+    pub(super) fn layout_id(&self) -> Result<Option<Uuid>, String> {
+        match self.layout_id.as_deref().map(str::trim) {
+            Some(id) if !id.is_empty() => Uuid::parse_str(id)
+                .map(Some)
+                .map_err(|_| format!("{id} is not a valid layout id.")),
+            _ => Ok(None),
+        }
+    }
+
+    /// The selected attachments, each listed once.
+    fn unique_attachment_ids(&self) -> Vec<Uuid> {
+        let mut ids = self.attachment_ids.clone();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
 }
 
 fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
 }
 
+/// Send the publish form back with `message`, nothing was published.
+fn rejected(message: impl Into<String>) -> HttpResponse {
+    FlashMessage::error(message).send();
+    not_replayable(see_other("/admin/newsletters"))
+}
+
+/// Replays do not reach [`publish_newsletter`], confirm that the issue was accepted all the same.
+pub async fn confirm_replayed_publication(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let response = next.call(req).await?;
+    if response.headers().contains_key(IDEMPOTENT_REPLAYED_HEADER) {
+        success_message().send();
+    }
+    Ok(response)
+}
+
 #[tracing::instrument(
     name = "Publish a newsletter issue",
     skip_all,
     fields(user_id=%&*user_id)
     )]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    form: UrlEncodedForm<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+    email_layout: web::Data<EmailLayoutSettings>,
+    attachment_settings: web::Data<AttachmentSettings>,
+    idempotency_claim: Option<web::ReqData<IdempotencyClaim>>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
-    let FormData {
-        title,
-        text_content,
-        html_content,
-        idempotency_key,
-    } = form.0;
-
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+    let layout_id = match form.layout_id() {
+        Ok(layout_id) => layout_id,
+        Err(e) => return Ok(rejected(e)),
+    };
+    if let Some(layout_id) = layout_id
+        && !layout_exists(&**pool, layout_id).await.map_err(e500)?
+    {
+        return Ok(rejected("The selected layout no longer exists."));
+    }
+    let layout = resolve_layout(&**pool, layout_id, &email_layout)
         .await
-        .map_err(e500)?
+        .map_err(e500)?;
+    let issue = match form
+        .content()
+        .and_then(|content| NewsletterIssue::parse(&form.title, content, &layout))
     {
-        NextAction::StartProcessing(t) => t,
-        NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
-            return Ok(saved_response);
-        }
+        Ok(issue) => issue,
+        Err(e) => return Ok(rejected(e)),
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    if form.publish_to_archive.is_none() && issue.links_to_archive() {
+        return Ok(rejected(
+            "Only issues published to the archive can link to {{ view_in_browser_url }}.",
+        ));
+    }
+    let attachment_ids = form.unique_attachment_ids();
+    match total_size(&**pool, &attachment_ids).await.map_err(e500)? {
+        None => return Ok(rejected("The selected attachment no longer exists.")),
+        Some(size) if size > attachment_settings.max_total_bytes as i64 => {
+            return Ok(rejected(format!(
+                "The selected attachments add up to more than the {} allowed per issue.",
+                display_size(attachment_settings.max_total_bytes)
+            )));
+        }
+        Some(_) => {}
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &issue,
+        layout_id,
+        *user_id,
+        form.publish_to_archive.is_some(),
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    attach_to_issue(&mut transaction, issue_id, &attachment_ids)
         .await
-        .context("Failed to store newsletter issue details")
+        .context("Failed to attach files to the newsletter issue")
         .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let recipient_count = enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
         .context("Failed to enqueue delivery tasks")
         .map_err(e500)?;
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
+    record_recipient_count(&mut transaction, issue_id, recipient_count)
         .await
+        .context("Failed to record the number of recipients")
+        .map_err(e500)?;
+    // An issue without recipients has nothing left to deliver.
+    complete_issue_if_delivered(&mut transaction, issue_id)
+        .await
+        .context("Failed to complete the newsletter issue")
+        .map_err(e500)?;
+    audit::record(
+        &mut *transaction,
+        Some(*user_id),
+        AuditAction::NewsletterPublished,
+        Some(&issue_id.to_string()),
+        &client,
+    )
+    .await
+    .context("Failed to record the publication in the audit log")
+    .map_err(e500)?;
+    let mut response = see_other("/admin/newsletters");
+    if let Some(claim) = idempotency_claim {
+        response = claim
+            .save_response(&mut transaction, response)
+            .await
+            .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new newsletter issue.")
         .map_err(e500)?;
     success_message().send();
 
@@ -71,11 +224,13 @@ pub async fn publish_newsletter(
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    issue: &NewsletterIssue,
+    layout_id: Option<Uuid>,
+    created_by: Uuid,
+    archived: bool,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let content = issue.content();
 
     sqlx::query!(
         r#"
@@ -84,14 +239,26 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
+            markdown_content,
+            layout_id,
+            created_by,
+            status,
+            archived,
+            slug,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, now())
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        issue.title(),
+        content.text,
+        content.html,
+        content.markdown,
+        layout_id,
+        created_by,
+        IssueStatus::Sending.as_str(),
+        archived,
+        issue.slug(newsletter_issue_id),
     )
     .execute(&mut **transaction)
     .await?;
@@ -99,22 +266,60 @@ async fn insert_newsletter_issue(
     Ok(newsletter_issue_id)
 }
 
+/// Returns the number of emails enqueued.
 #[tracing::instrument(skip_all)]
 async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+) -> Result<u64, sqlx::Error> {
+    // Lets the delivery of each email be traced back to the request that published the issue.
+    let trace_context = TraceContext::current();
+    let n_enqueued = sqlx::query!(
         r#"
             INSERT INTO issue_delivery_queue (
                 newsletter_issue_id,
-                subscriber_email
+                subscriber_id,
+                traceparent,
+                tracestate
             )
-            SELECT $1, email
+            SELECT $1, id, $2, $3
             FROM subscriptions
-            WHERE status = 'confirmed'
+            WHERE
+                status = 'confirmed' AND
+                NOT EXISTS (
+                    SELECT 1
+                    FROM suppressions
+                    WHERE
+                        (kind = 'email' AND value = lower(subscriptions.email)) OR
+                        (kind = 'domain' AND value = lower(split_part(subscriptions.email, '@', 2)))
+                )
+        "#,
+        newsletter_issue_id,
+        trace_context.traceparent,
+        trace_context.tracestate,
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_enqueued)
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn record_recipient_count(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    recipient_count: u64,
+) -> Result<(), sqlx::Error> {
+    let recipient_count = i32::try_from(recipient_count).unwrap_or(i32::MAX);
+    sqlx::query!(
+        r#"
+            UPDATE newsletter_issues
+            SET recipient_count = $2
+            WHERE newsletter_issue_id = $1
         "#,
         newsletter_issue_id,
+        recipient_count
     )
     .execute(&mut **transaction)
     .await?;
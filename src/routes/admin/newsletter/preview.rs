@@ -0,0 +1,80 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_lab::extract::UrlEncodedForm;
+use sqlx::PgPool;
+
+use super::{get::publish_form_html, post::FormData};
+use crate::{
+    attachment::get_attachments,
+    configuration::{EmailLayoutSettings, HtmlSanitizerSettings},
+    email_layout::{get_layouts, resolve_layout},
+    html::{escape_attribute, escape_text, sanitize},
+    templating::{ContentKind, MergeFields, render},
+    utils::{e400, e500},
+};
+
+/// Show the HTML and plain-text renderings of a newsletter issue without publishing it,
+/// wrapped in the layout it would be sent with. Merge fields are filled in with example values.
+pub async fn preview_newsletter(
+    form: UrlEncodedForm<FormData>,
+    pool: web::Data<PgPool>,
+    email_layout: web::Data<EmailLayoutSettings>,
+    html_sanitizer: web::Data<HtmlSanitizerSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let content = form.content().map_err(e400)?;
+    let layout_id = form.layout_id().map_err(e400)?;
+    let layout = resolve_layout(&**pool, layout_id, &email_layout)
+        .await
+        .map_err(e500)?;
+    let fields = MergeFields::example();
+    let render = |source: &str, kind| {
+        let mut body = render(source, kind, &fields)?;
+        if let ContentKind::Html = kind {
+            body = sanitize(&body, &html_sanitizer);
+        }
+        layout.wrap(&body, kind, Some(&fields))
+    };
+
+    let html_preview = match render(&content.html, ContentKind::Html) {
+        Ok(html) => format!(
+            r#"<iframe sandbox srcdoc="{}" title="HTML preview" width="100%" height="400"></iframe>"#,
+            escape_attribute(html)
+        ),
+        Err(e) => format!(
+            "<p><i>The HTML content is not a valid template: {}</i></p>",
+            escape_text(e.to_string())
+        ),
+    };
+    let text_preview = match render(&content.text, ContentKind::Text) {
+        Ok(text) => format!("<pre>{}</pre>", escape_text(text)),
+        Err(e) => format!(
+            "<p><i>The text content is not a valid template: {}</i></p>",
+            escape_text(e.to_string())
+        ),
+    };
+    let layouts = get_layouts(&pool).await.map_err(e500)?;
+    let attachments = get_attachments(&pool).await.map_err(e500)?;
+    let form_html = publish_form_html(&form, &layouts, &attachments);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Preview Newsletter</title>
+            </head>
+            <body>
+                <h2>{}</h2>
+                <h3>HTML</h3>
+                {html_preview}
+                <h3>Plain text</h3>
+                {text_preview}
+                {form_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+            escape_text(&form.title),
+        )))
+}
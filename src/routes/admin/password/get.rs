@@ -2,6 +2,7 @@ use actix_web::{HttpResponse, http::header::ContentType};
 use actix_web_flash_messages::IncomingFlashMessages;
 
 use crate::{
+    html::flash_messages_html,
     session_state::TypedSession,
     utils::{e500, see_other},
 };
@@ -13,10 +14,7 @@ pub async fn change_password_form(
     if session.get_user_id().map_err(e500)?.is_none() {
         return Ok(see_other("/login"));
     };
-    let mut msg_html = String::new();
-    for m in flash_messages.iter() {
-        msg_html.push_str(&format!("<p><i>{}</i></p>\n", m.content()));
-    }
+    let msg_html = flash_messages_html(&flash_messages);
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -1,9 +1,11 @@
 use actix_web::{HttpResponse, web};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
 
 use crate::{
+    audit::{self, AuditAction, ClientInfo},
     authentication::{AuthError, Credentials, UserId, validate_credentials},
     routes::admin::dashboard::get_username,
     utils::{e500, see_other},
@@ -20,6 +22,7 @@ pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    client: ClientInfo,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
@@ -45,8 +48,28 @@ pub async fn change_password(
         };
     }
 
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
+    // The change is only committed along with its audit log entry.
+    let mut transaction = pool
+        .begin()
         .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    crate::authentication::change_password(*user_id, form.0.new_password, &mut *transaction)
+        .await
+        .map_err(e500)?;
+    audit::record(
+        &mut *transaction,
+        Some(*user_id),
+        AuditAction::PasswordChanged,
+        None,
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the password.")
         .map_err(e500)?;
     FlashMessage::error("Your password has been changed.").send();
 
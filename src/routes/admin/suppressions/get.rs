@@ -0,0 +1,119 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::{
+    html::{escape_attribute, escape_text, flash_messages_html},
+    suppression::{find_suppression, get_suppressions},
+    utils::e500,
+};
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    address: Option<String>,
+}
+
+pub async fn suppression_list(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let msg_html = flash_messages_html(&flash_messages);
+
+    let mut lookup_html = String::new();
+    if let Some(address) = query.0.address.filter(|a| !a.trim().is_empty()) {
+        let address = address.trim();
+        let outcome = match find_suppression(&**pool, address).await.map_err(e500)? {
+            Some(s) => format!(
+                "{} is suppressed by the {} entry <b>{}</b> (reason: {}).",
+                escape_text(address),
+                s.kind,
+                escape_text(&s.value),
+                s.reason
+            ),
+            None => format!("{} is not suppressed.", escape_text(address)),
+        };
+        lookup_html.push_str(&format!("<p>{outcome}</p>\n"));
+    }
+
+    let mut rows_html = String::new();
+    for s in get_suppressions(&pool).await.map_err(e500)? {
+        rows_html.push_str(&format!(
+            r#"<tr>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>
+                    <form action="/admin/suppressions/remove" method="post">
+                        <input type="hidden" name="suppression_id" value="{}">
+                        <button type="submit">Remove</button>
+                    </form>
+                </td>
+            </tr>
+            "#,
+            escape_text(&s.value),
+            s.kind,
+            s.reason,
+            escape_text(s.note.as_deref().unwrap_or_default()),
+            s.created_at.format("%Y-%m-%d %H:%M UTC"),
+            escape_attribute(s.suppression_id.to_string()),
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Suppression list</title>
+            </head>
+            <body>
+                {msg_html}
+                <form action="/admin/suppressions" method="get">
+                    <label>Check an address
+                        <input type="text" name="address" placeholder="someone@example.com">
+                    </label>
+                    <button type="submit">Check</button>
+                </form>
+                {lookup_html}
+                <form action="/admin/suppressions" method="post">
+                    <label>Email address or domain
+                        <input type="text" name="address" placeholder="someone@example.com or example.com">
+                    </label>
+                    <br>
+                    <label>Reason
+                        <select name="reason">
+                            <option value="manual">Manual</option>
+                            <option value="bounce">Bounce</option>
+                            <option value="complaint">Complaint</option>
+                            <option value="unsubscribe">Unsubscribe</option>
+                        </select>
+                    </label>
+                    <br>
+                    <label>Note
+                        <input type="text" name="note" placeholder="Optional note">
+                    </label>
+                    <br>
+                    <button type="submit">Suppress</button>
+                </form>
+                <table>
+                    <tr>
+                        <th>Email or domain</th>
+                        <th>Kind</th>
+                        <th>Reason</th>
+                        <th>Note</th>
+                        <th>Added</th>
+                        <th></th>
+                    </tr>
+                    {rows_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
@@ -0,0 +1,137 @@
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditAction, ClientInfo},
+    authentication::UserId,
+    domain::{SuppressionReason, SuppressionTarget},
+    suppression::{delete_suppression, insert_suppression},
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    address: String,
+    reason: String,
+    #[serde(default)]
+    note: String,
+}
+
+#[tracing::instrument(name = "Add to the suppression list", skip_all)]
+pub async fn add_to_suppression_list(
+    form: web::Form<AddFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AddFormData {
+        address,
+        reason,
+        note,
+    } = form.0;
+    let target = match SuppressionTarget::parse(&address) {
+        Ok(target) => target,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let reason = match SuppressionReason::try_from(reason) {
+        Ok(reason) => reason,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+    let note = Some(note.trim()).filter(|n| !n.is_empty());
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let inserted = insert_suppression(&mut *transaction, &target, reason, note)
+        .await
+        .map_err(e500)?;
+    if inserted {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::SuppressionAdded,
+            Some(&target.value()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to add a suppression entry.")
+        .map_err(e500)?;
+
+    if inserted {
+        FlashMessage::info(format!(
+            "{} has been added to the suppression list.",
+            target.value()
+        ))
+        .send();
+    } else {
+        FlashMessage::info(format!(
+            "{} is already on the suppression list.",
+            target.value()
+        ))
+        .send();
+    }
+
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    suppression_id: Uuid,
+}
+
+#[tracing::instrument(name = "Remove from the suppression list", skip_all)]
+pub async fn remove_from_suppression_list(
+    form: web::Form<RemoveFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let deleted = delete_suppression(&mut *transaction, form.suppression_id)
+        .await
+        .map_err(e500)?;
+    if deleted {
+        audit::record(
+            &mut *transaction,
+            Some(**user_id),
+            AuditAction::SuppressionRemoved,
+            Some(&form.suppression_id.to_string()),
+            &client,
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to remove a suppression entry.")
+        .map_err(e500)?;
+
+    if deleted {
+        FlashMessage::info("The entry has been removed from the suppression list.").send();
+    } else {
+        FlashMessage::error("The entry was not found on the suppression list.").send();
+    }
+
+    Ok(see_other("/admin/suppressions"))
+}
@@ -0,0 +1,121 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    configuration::{EmailLayoutSettings, HtmlSanitizerSettings},
+    email_layout::resolve_layout,
+    html::{escape_attribute, escape_text, sanitize},
+    newsletter_issue::{get_archive, get_archived_issue},
+    startup::ApplicationBaseUrl,
+    templating::{ContentKind, MergeFields, render},
+    utils::e500,
+};
+
+#[tracing::instrument(name = "Show the newsletter archive", skip(pool))]
+pub async fn archive(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut items_html = String::new();
+    for issue in get_archive(&pool).await.map_err(e500)? {
+        let published_at = issue
+            .published_at
+            .map(|t| t.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        items_html.push_str(&format!(
+            r#"<li><a href="/archive/{}">{}</a> {published_at}</li>"#,
+            escape_attribute(urlencoding::encode(&issue.slug)),
+            escape_text(&issue.title),
+        ));
+    }
+    if items_html.is_empty() {
+        items_html.push_str("<li>No issues yet.</li>");
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Newsletter archive</title>
+            </head>
+            <body>
+                <h1>Newsletter archive</h1>
+                <ul>
+                    {items_html}
+                </ul>
+                <p><a href="/">Subscribe</a></p>
+            </body>
+            </html>"#,
+        )))
+}
+
+/// The HTML content of an issue, for a reader who is not subscribed.
+pub(super) fn public_html(
+    html_content: &str,
+    base_url: &str,
+    slug: &str,
+    html_sanitizer: &HtmlSanitizerSettings,
+) -> Result<String, minijinja::Error> {
+    let html = render(
+        html_content,
+        ContentKind::Html,
+        &MergeFields::public(base_url, slug),
+    )?;
+    Ok(sanitize(&html, html_sanitizer))
+}
+
+/// The issue is rendered as it was sent, for a reader who is not subscribed: no merge
+/// field reveals a subscriber, and the layout is sanitized like the content.
+#[tracing::instrument(
+    name = "Show an archived newsletter issue",
+    skip(pool, email_layout, html_sanitizer, base_url)
+)]
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+    email_layout: web::Data<EmailLayoutSettings>,
+    html_sanitizer: web::Data<HtmlSanitizerSettings>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_archived_issue(&pool, &slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let layout = resolve_layout(&**pool, issue.layout_id, &email_layout)
+        .await
+        .map_err(e500)?;
+    let body = public_html(&issue.html_content, &base_url.0, &slug, &html_sanitizer)
+        .context("Failed to render the HTML content of the issue")
+        .map_err(e500)?;
+    let body = layout
+        .wrap(&body, ContentKind::Html, None)
+        .context("Failed to render the HTML layout")
+        .map_err(e500)?;
+    let body = sanitize(&body, &html_sanitizer);
+    let published_at = issue
+        .published_at
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+            </head>
+            <body>
+                <h1>{title}</h1>
+                <p>{published_at}</p>
+                <article>
+                    {body}
+                </article>
+                <p><a href="/archive">&lt;- All issues</a> | <a href="/">Subscribe</a></p>
+            </body>
+            </html>"#,
+            title = escape_text(&issue.title),
+        )))
+}
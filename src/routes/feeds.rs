@@ -0,0 +1,240 @@
+use actix_web::{
+    HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
+    http::header::{ETag, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, LastModified},
+    web,
+};
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use super::archive::public_html;
+use crate::{
+    configuration::{FeedSettings, HtmlSanitizerSettings},
+    html::{escape_attribute, escape_text},
+    newsletter_issue::{FeedEntry, archive_revision, get_feed_entries},
+    startup::ApplicationBaseUrl,
+    utils::e500,
+};
+
+const ATOM_CONTENT_TYPE: &str = "application/atom+xml; charset=utf-8";
+const RSS_CONTENT_TYPE: &str = "application/rss+xml; charset=utf-8";
+
+/// The validators of a feed, derived from the state of the archive rather than from the
+/// feed itself: readers polling an unchanged feed are answered before it is rendered.
+struct FeedVersion {
+    etag: EntityTag,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+async fn feed_version(
+    pool: &PgPool,
+    content_type: &str,
+    settings: &FeedSettings,
+    base_url: &str,
+) -> Result<FeedVersion, anyhow::Error> {
+    let revision = archive_revision(pool)
+        .await
+        .context("Failed to get the revision of the archive")?;
+    let mut hasher = Sha256::new();
+    for part in [
+        content_type,
+        base_url,
+        &settings.title,
+        &settings.description,
+        &settings.max_entries.to_string(),
+        &revision.issue_count.to_string(),
+        &revision
+            .last_modified
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Micros, true))
+            .unwrap_or_default(),
+    ] {
+        hasher.update(part);
+        hasher.update(b"\n");
+    }
+    Ok(FeedVersion {
+        etag: EntityTag::new_strong(hex::encode(hasher.finalize())),
+        last_modified: revision.last_modified,
+    })
+}
+
+/// The feed entries with their public HTML content.
+async fn load_entries(
+    pool: &PgPool,
+    settings: &FeedSettings,
+    base_url: &str,
+    html_sanitizer: &HtmlSanitizerSettings,
+) -> Result<Vec<(FeedEntry, String)>, anyhow::Error> {
+    let mut entries = Vec::new();
+    for entry in get_feed_entries(pool, settings.max_entries).await? {
+        let html = public_html(&entry.html_content, base_url, &entry.slug, html_sanitizer)
+            .with_context(|| format!("Failed to render issue {}", entry.newsletter_issue_id))?;
+        entries.push((entry, html));
+    }
+    Ok(entries)
+}
+
+#[tracing::instrument(name = "Show the Atom feed", skip_all)]
+pub async fn atom_feed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings: web::Data<FeedSettings>,
+    html_sanitizer: web::Data<HtmlSanitizerSettings>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let version = feed_version(&pool, ATOM_CONTENT_TYPE, &settings, base_url)
+        .await
+        .map_err(e500)?;
+    if is_cached(&req, &version) {
+        return Ok(not_modified(&version));
+    }
+    let entries = load_entries(&pool, &settings, base_url, &html_sanitizer)
+        .await
+        .map_err(e500)?;
+    let rfc3339 = |t: DateTime<Utc>| t.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let mut entries_xml = String::new();
+    for (entry, html) in &entries {
+        entries_xml.push_str(&format!(
+            r#"
+    <entry>
+        <title>{}</title>
+        <link href="{}"/>
+        <id>urn:uuid:{}</id>
+        <published>{published_at}</published>
+        <updated>{published_at}</updated>
+        <content type="html">{}</content>
+    </entry>"#,
+            escape_text(&entry.title),
+            escape_attribute(format!("{base_url}/archive/{}", entry.slug)),
+            entry.newsletter_issue_id,
+            escape_text(html),
+            published_at = rfc3339(entry.published_at),
+        ));
+    }
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>{title}</title>
+    <subtitle>{}</subtitle>
+    <link href="{archive_url}"/>
+    <link rel="self" href="{}"/>
+    <id>{archive_url}</id>
+    <updated>{}</updated>
+    <author><name>{title}</name></author>{entries_xml}
+</feed>
+"#,
+        escape_text(&settings.description),
+        escape_attribute(format!("{base_url}/feed.xml")),
+        rfc3339(version.last_modified.unwrap_or(DateTime::UNIX_EPOCH)),
+        title = escape_text(&settings.title),
+        archive_url = escape_attribute(format!("{base_url}/archive")),
+    );
+
+    Ok(with_validators(HttpResponse::Ok(), &version)
+        .insert_header(("Content-Type", ATOM_CONTENT_TYPE))
+        .body(body))
+}
+
+#[tracing::instrument(name = "Show the RSS feed", skip_all)]
+pub async fn rss_feed(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings: web::Data<FeedSettings>,
+    html_sanitizer: web::Data<HtmlSanitizerSettings>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let version = feed_version(&pool, RSS_CONTENT_TYPE, &settings, base_url)
+        .await
+        .map_err(e500)?;
+    if is_cached(&req, &version) {
+        return Ok(not_modified(&version));
+    }
+    let entries = load_entries(&pool, &settings, base_url, &html_sanitizer)
+        .await
+        .map_err(e500)?;
+
+    let mut items_xml = String::new();
+    for (entry, html) in &entries {
+        items_xml.push_str(&format!(
+            r#"
+        <item>
+            <title>{}</title>
+            <link>{}</link>
+            <guid isPermaLink="false">{}</guid>
+            <pubDate>{}</pubDate>
+            <description>{}</description>
+        </item>"#,
+            escape_text(&entry.title),
+            escape_text(format!("{base_url}/archive/{}", entry.slug)),
+            entry.newsletter_issue_id,
+            entry.published_at.to_rfc2822(),
+            escape_text(html),
+        ));
+    }
+    let last_build_date = match version.last_modified {
+        Some(t) => format!(
+            "\n        <lastBuildDate>{}</lastBuildDate>",
+            t.to_rfc2822()
+        ),
+        None => String::new(),
+    };
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+    <channel>
+        <title>{}</title>
+        <link>{}</link>
+        <description>{}</description>
+        <atom:link href="{}" rel="self" type="application/rss+xml"/>{last_build_date}{items_xml}
+    </channel>
+</rss>
+"#,
+        escape_text(&settings.title),
+        escape_text(format!("{base_url}/archive")),
+        escape_text(&settings.description),
+        escape_attribute(format!("{base_url}/rss.xml")),
+    );
+
+    Ok(with_validators(HttpResponse::Ok(), &version)
+        .insert_header(("Content-Type", RSS_CONTENT_TYPE))
+        .body(body))
+}
+
+/// Whether the feed reader already has this version of the feed.
+///
+/// As required by RFC 9110, `If-Modified-Since` is only looked at when there is
+/// no `If-None-Match`.
+fn is_cached(req: &HttpRequest, version: &FeedVersion) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&version.etag)),
+        None => match (req.get_header::<IfModifiedSince>(), version.last_modified) {
+            (Some(IfModifiedSince(since)), Some(last_modified)) => {
+                // HTTP dates are precise to the second.
+                let since = DateTime::<Utc>::from(std::time::SystemTime::from(since));
+                last_modified.timestamp() <= since.timestamp()
+            }
+            _ => false,
+        },
+    }
+}
+
+fn not_modified(version: &FeedVersion) -> HttpResponse {
+    with_validators(HttpResponse::NotModified(), version).finish()
+}
+
+fn with_validators(
+    mut response: HttpResponseBuilder,
+    version: &FeedVersion,
+) -> HttpResponseBuilder {
+    response.insert_header(ETag(version.etag.clone()));
+    if let Some(last_modified) = version.last_modified {
+        response.insert_header(LastModified(HttpDate::from(std::time::SystemTime::from(
+            last_modified,
+        ))));
+    }
+    response
+}
@@ -1,7 +1,15 @@
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpResponse, http::header::ContentType, web};
 
-pub async fn home() -> HttpResponse {
+use uuid::Uuid;
+
+use crate::{form_token::FormTokens, html::escape_attribute};
+
+pub async fn home(form_tokens: web::Data<FormTokens>) -> HttpResponse {
     HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            include_str!("home.html"),
+            form_token = escape_attribute(form_tokens.issue()),
+            idempotency_key = Uuid::new_v4()
+        ))
 }
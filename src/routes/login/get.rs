@@ -1,11 +1,10 @@
 use actix_web::{HttpResponse, http::header::ContentType};
 use actix_web_flash_messages::IncomingFlashMessages;
 
+use crate::html::flash_messages_html;
+
 pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html = String::new();
-    for m in flash_messages.iter() {
-        error_html.push_str(&format!("<p><i>{}</i></p>", m.content()));
-    }
+    let error_html = flash_messages_html(&flash_messages);
     let html = format!(
         r#"<!DOCTYPE html>
             <html lang="en">
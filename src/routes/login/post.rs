@@ -13,7 +13,9 @@ use secrecy::SecretString;
 use sqlx::PgPool;
 
 use crate::{
+    audit::{self, AuditAction, ClientInfo},
     authentication::{AuthError, Credentials, validate_credentials},
+    metrics::METRICS,
     routes::error_chain_fmt,
     session_state::TypedSession,
 };
@@ -63,21 +65,30 @@ impl ResponseError for LoginError {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, client),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
     )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    client: ClientInfo,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let username = form.0.username;
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
+            METRICS
+                .login_attempts_total
+                .with_label_values(&["success"])
+                .inc();
             tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            audit::record(&**pool, Some(user_id), AuditAction::Login, None, &client)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
 
             session.renew();
             session
@@ -89,10 +100,25 @@ pub async fn login(
                 .finish())
         }
         Err(e) => {
-            let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
-                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+            let (outcome, e) = match e {
+                AuthError::InvalidCredentials(_) => ("failure", LoginError::AuthError(e.into())),
+                AuthError::UnexpectedError(_) => ("error", LoginError::UnexpectedError(e.into())),
             };
+            METRICS
+                .login_attempts_total
+                .with_label_values(&[outcome])
+                .inc();
+            if let LoginError::AuthError(_) = e {
+                audit::record(
+                    &**pool,
+                    None,
+                    AuditAction::LoginFailed,
+                    Some(&username),
+                    &client,
+                )
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            }
 
             Err(login_redirect(e))
         }
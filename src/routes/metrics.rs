@@ -0,0 +1,70 @@
+use actix_web::{
+    HttpRequest, HttpResponse,
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    web,
+};
+use prometheus::TEXT_FORMAT;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::{
+    configuration::MetricsSettings, confirmation_outbox::outbox_depth,
+    delivery_log::get_recent_issue_delivery_counts, issue_delivery_worker::queue_depth,
+    metrics::METRICS, utils::e500,
+};
+
+pub async fn metrics(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings: web::Data<MetricsSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(bearer_token) = &settings.bearer_token else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if !is_authorized(&req, bearer_token.expose_secret()) {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, "Bearer"))
+            .finish());
+    }
+    // The queues are shared by all workers, so their depth is read from the database
+    // rather than tracked in memory.
+    let depth = queue_depth(&pool).await.map_err(e500)?;
+    METRICS.issue_delivery_queue_depth.set(depth);
+    let depth = outbox_depth(&pool).await.map_err(e500)?;
+    METRICS.confirmation_email_outbox_depth.set(depth);
+    // Older issues drop out of the gauges.
+    let counts = get_recent_issue_delivery_counts(&pool)
+        .await
+        .map_err(e500)?;
+    METRICS.issue_emails_sent.reset();
+    METRICS.issue_emails_failed.reset();
+    for c in counts {
+        let issue = c.newsletter_issue_id.to_string();
+        METRICS
+            .issue_emails_sent
+            .with_label_values(&[issue.as_str()])
+            .set(c.n_sent);
+        METRICS
+            .issue_emails_failed
+            .with_label_values(&[issue.as_str()])
+            .set(c.n_failed);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(TEXT_FORMAT)
+        .body(METRICS.encode().map_err(e500)?))
+}
+
+fn is_authorized(req: &HttpRequest, bearer_token: &str) -> bool {
+    let Some(sent_token) = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Comparing digests rather than the tokens does not reveal how much of the token matched.
+    Sha256::digest(sent_token.as_bytes()) == Sha256::digest(bearer_token.as_bytes())
+}
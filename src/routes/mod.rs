@@ -1,16 +1,28 @@
 mod admin;
+mod archive;
+mod feeds;
 mod health_check;
 mod home;
 mod login;
+mod metrics;
+mod ready;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_preferences;
+mod subscriptions_unsubscribe;
 
 pub use admin::*;
+pub use archive::{archive, archived_issue};
+pub use feeds::{atom_feed, rss_feed};
 pub use health_check::health_check;
 pub use home::home;
 pub use login::{login, login_form};
+pub use metrics::metrics;
+pub use ready::ready;
 pub use subscriptions::subscribe;
-pub use subscriptions_confirm::confirm;
+pub use subscriptions_confirm::{confirm, resend_confirmation};
+pub use subscriptions_preferences::subscription_preferences;
+pub use subscriptions_unsubscribe::{unsubscribe, unsubscribe_form};
 
 pub fn error_chain_fmt(
     e: &impl std::error::Error,
@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use sqlx::{PgPool, migrate::Migrator};
+
+use crate::{configuration::ReadinessSettings, issue_delivery_worker::latest_heartbeat_age};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(serde::Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Up,
+    Down,
+}
+
+#[derive(serde::Serialize)]
+struct Check {
+    status: Status,
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct Checks {
+    postgres: Check,
+    redis: Check,
+    migrations: Check,
+    worker: Check,
+}
+
+#[derive(serde::Serialize)]
+struct Readiness {
+    status: &'static str,
+    checks: Checks,
+}
+
+/// Readiness probe: unlike `/health_check`, it fails when a dependency the
+/// instance needs to serve traffic is unavailable.
+#[tracing::instrument(name = "Readiness check", skip_all)]
+pub async fn ready(
+    pool: web::Data<PgPool>,
+    redis: web::Data<ConnectionManager>,
+    settings: web::Data<ReadinessSettings>,
+) -> HttpResponse {
+    let timeout = settings.check_timeout();
+    let (postgres, redis, migrations, worker) = tokio::join!(
+        check(timeout, check_postgres(&pool)),
+        check(timeout, check_redis(redis.get_ref().clone())),
+        check(timeout, check_migrations(&pool)),
+        check(
+            timeout,
+            check_worker(&pool, settings.max_worker_heartbeat_age())
+        ),
+    );
+    let checks = Checks {
+        postgres,
+        redis,
+        migrations,
+        worker,
+    };
+
+    let all_up = [
+        &checks.postgres,
+        &checks.redis,
+        &checks.migrations,
+        &checks.worker,
+    ]
+    .iter()
+    .all(|c| c.status == Status::Up);
+    if all_up {
+        HttpResponse::Ok().json(Readiness {
+            status: "ready",
+            checks,
+        })
+    } else {
+        HttpResponse::ServiceUnavailable().json(Readiness {
+            status: "not_ready",
+            checks,
+        })
+    }
+}
+
+/// Run a dependency check, which yields an optional detail on success.
+async fn check(
+    timeout: Duration,
+    f: impl Future<Output = Result<Option<String>, anyhow::Error>>,
+) -> Check {
+    let start = Instant::now();
+    let outcome = tokio::time::timeout(timeout, f)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {timeout:?}")));
+    let latency_ms = start.elapsed().as_millis();
+    match outcome {
+        Ok(detail) => Check {
+            status: Status::Up,
+            latency_ms,
+            detail,
+        },
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, error.message = %e, "Dependency check failed");
+            Check {
+                status: Status::Down,
+                latency_ms,
+                detail: Some(format!("{e:#}")),
+            }
+        }
+    }
+}
+
+async fn check_postgres(pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    sqlx::query!("SELECT 1 AS one")
+        .fetch_one(pool)
+        .await
+        .context("Failed to query Postgres")?;
+    Ok(None)
+}
+
+async fn check_redis(mut connection: ConnectionManager) -> Result<Option<String>, anyhow::Error> {
+    redis::cmd("PING")
+        .query_async::<String>(&mut connection)
+        .await
+        .context("Failed to ping Redis")?;
+    Ok(None)
+}
+
+/// The schema must be at least as recent as the migrations this binary was built with.
+async fn check_migrations(pool: &PgPool) -> Result<Option<String>, anyhow::Error> {
+    let expected = MIGRATOR.iter().map(|m| m.version).max().unwrap_or_default();
+    // `_sqlx_migrations` is managed by sqlx itself, hence the unchecked query.
+    let applied: Option<i64> =
+        sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations WHERE success")
+            .fetch_one(pool)
+            .await
+            .context("Failed to read the applied migrations")?;
+    match applied {
+        Some(applied) if applied >= expected => Ok(Some(format!("version {applied}"))),
+        applied => anyhow::bail!(
+            "Migration {expected} has not been applied, the latest is {}",
+            applied.map_or("none".into(), |v| v.to_string())
+        ),
+    }
+}
+
+async fn check_worker(pool: &PgPool, max_age: Duration) -> Result<Option<String>, anyhow::Error> {
+    match latest_heartbeat_age(pool)
+        .await
+        .context("Failed to read worker heartbeats")?
+    {
+        Some(age) if age <= max_age => Ok(Some(format!(
+            "last heartbeat {:.1}s ago",
+            age.as_secs_f64()
+        ))),
+        Some(age) => anyhow::bail!(
+            "The last worker heartbeat was {:.1}s ago",
+            age.as_secs_f64()
+        ),
+        None => anyhow::bail!("No worker heartbeat has been recorded"),
+    }
+}
@@ -6,9 +6,12 @@ use sqlx::{PgPool, Postgres, Transaction, types::chrono::Utc};
 use uuid::Uuid;
 
 use crate::{
+    confirmation_outbox::enqueue_confirmation_email,
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
-    startup::ApplicationBaseUrl,
+    form_token::{FormTokenError, FormTokens},
+    idempotency::IdempotencyClaim,
+    metrics::METRICS,
+    rate_limit::{Bucket, RateLimitExceeded, RateLimiter},
 };
 
 use super::error_chain_fmt;
@@ -44,6 +47,10 @@ pub enum SubscribeError {
     #[error("{0}")]
     Validation(String),
     #[error(transparent)]
+    RateLimited(#[from] RateLimitExceeded),
+    #[error(transparent)]
+    Rejected(#[from] FormTokenError),
+    #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
 
@@ -56,16 +63,29 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::Validation(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::Validation(_) | SubscribeError::Rejected(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::RateLimited(e) => e.status_code(),
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::RateLimited(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).body(self.to_string()),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// Honeypot: hidden on the home page, so only bots fill it in.
+    #[serde(default)]
+    website: String,
+    #[serde(default)]
+    form_token: String,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -81,7 +101,7 @@ impl TryFrom<FormData> for NewSubscriber {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip_all,
     fields(
     subscriber_email = %form.email,
     subscriber_name= %form.name
@@ -90,10 +110,40 @@ impl TryFrom<FormData> for NewSubscriber {
 pub async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
+    rate_limiter: web::Data<RateLimiter>,
+    form_tokens: web::Data<FormTokens>,
+    idempotency_claim: Option<web::ReqData<IdempotencyClaim>>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::Validation)?;
+    let count_rejection = |reason: &str| {
+        METRICS
+            .subscriptions_rejected_total
+            .with_label_values(&[reason])
+            .inc();
+    };
+    let form_token = if form_tokens.enabled() {
+        if !form.website.is_empty() {
+            // Let bots believe they succeeded, so they do not adapt.
+            count_rejection("honeypot");
+            return Ok(HttpResponse::Ok().finish());
+        }
+        let token = form_tokens
+            .verify(&form.form_token)
+            .inspect_err(|e| count_rejection(e.reason()))?;
+        Some(token)
+    } else {
+        None
+    };
+    let new_subscriber: NewSubscriber = form.0.try_into().map_err(SubscribeError::Validation)?;
+    // Only spent once the form is valid, so that fixing a typo does not require a reload.
+    if let Some(token) = form_token {
+        form_tokens
+            .mark_as_used(&token)
+            .await
+            .inspect_err(|e| count_rejection(e.reason()))?;
+    }
+    rate_limiter
+        .check(Bucket::SubscribeByEmail, new_subscriber.email.as_ref())
+        .await?;
 
     let mut transaction = pool
         .begin()
@@ -108,22 +158,21 @@ pub async fn subscribe(
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
+    enqueue_confirmation_email(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to enqueue the confirmation email for a new subscriber.")?;
+    let mut response = HttpResponse::Ok().finish();
+    if let Some(claim) = idempotency_claim {
+        response = claim.save_response(&mut transaction, response).await?;
+    }
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
+    METRICS.subscriptions_created_total.inc();
 
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
-
-    Ok(HttpResponse::Ok().finish())
+    Ok(response)
 }
 
 #[tracing::instrument(
@@ -138,8 +187,8 @@ pub async fn store_token(
     sqlx::query!(
         r#"
             INSERT INTO subscription_tokens 
-                (subscription_token, subscriber_id)
-            VALUES ($1, $2)
+                (subscription_token, subscriber_id, created_at)
+            VALUES ($1, $2, now())
             "#,
         subscription_token,
         subscriber_id
@@ -154,32 +203,6 @@ pub async fn store_token(
     Ok(())
 }
 
-#[tracing::instrument(
-    name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
-)]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
-    base_url: &str,
-    subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link =
-        format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {confirmation_link} to confirm your subscription."
-    );
-
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-            Click <a href=\"{confirmation_link}\">here</a> to confirm your subscription."
-    );
-
-    email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
-}
-
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(transaction, new_subscriber)
@@ -211,7 +234,7 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
-fn generate_subscription_token() -> String {
+pub(super) fn generate_subscription_token() -> String {
     let mut rng = rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
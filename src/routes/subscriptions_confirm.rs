@@ -1,35 +1,356 @@
-use actix_web::{HttpResponse, web};
-use sqlx::PgPool;
+use actix_web::{HttpResponse, http::StatusCode, http::header::ContentType, web};
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::subscriptions::{generate_subscription_token, store_token};
+use crate::{
+    configuration::{ConfirmationSettings, EmailLayoutSettings, WelcomeEmailSettings},
+    confirmation_outbox::enqueue_confirmation_email,
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    email_layout::resolve_layout,
+    html::{escape_attribute, escape_text},
+    metrics::METRICS,
+    rate_limit::{Bucket, RateLimiter},
+    startup::ApplicationBaseUrl,
+    suppression::find_suppression,
+    templating::{ContentKind, MergeFields, render},
+    utils::e500,
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
+
+/// The subscriber a token belongs to.
+struct TokenOwner {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    token_created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, pool, email_client, base_url, email_layout, settings)
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_layout: web::Data<EmailLayoutSettings>,
+    settings: web::Data<ConfirmationSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let owner = match get_token_owner(&pool, &parameters.subscription_token)
+        .await
+        .map_err(e500)?
+    {
+        Some(owner) => owner,
+        None => return Ok(invalid_link_page()),
     };
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(subscriber_id, &pool).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
+    match owner.status.as_str() {
+        "confirmed" => Ok(already_confirmed_page()),
+        "pending_confirmation"
+            if owner.token_created_at < Utc::now() - Duration::hours(settings.link_ttl_hours) =>
+        {
+            Ok(expired_link_page(&parameters.subscription_token))
+        }
+        "pending_confirmation" => {
+            if !confirm_subscriber(owner.subscriber_id, &pool)
+                .await
+                .map_err(e500)?
+            {
+                // Another request with the same link got there first, e.g. a double click.
+                return Ok(already_confirmed_page());
             }
-            HttpResponse::Ok().finish()
+            METRICS.subscriptions_confirmed_total.inc();
+            if settings.welcome_email.enabled
+                && let Err(e) = send_welcome_email(
+                    &pool,
+                    &email_client,
+                    &owner,
+                    &parameters.subscription_token,
+                    &base_url.0,
+                    &email_layout,
+                    &settings.welcome_email,
+                )
+                .await
+            {
+                // The subscription is confirmed all the same.
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send the welcome email",
+                );
+            }
+            Ok(confirmed_page())
+        }
+        // E.g. subscribers who unsubscribed since.
+        _ => Ok(invalid_link_page()),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    subscription_token: String,
+}
+
+/// Send a fresh confirmation link, in place of an expired one.
+///
+/// The expired token is deleted along the way, so that each link can only be
+/// traded for a new one once.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, pool, rate_limiter, settings)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pool: web::Data<PgPool>,
+    rate_limiter: web::Data<RateLimiter>,
+    settings: web::Data<ConfirmationSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let owner = match get_token_owner(&pool, &form.subscription_token)
+        .await
+        .map_err(e500)?
+    {
+        Some(owner) => owner,
+        None => return Ok(invalid_link_page()),
+    };
+    match owner.status.as_str() {
+        "confirmed" => return Ok(already_confirmed_page()),
+        "pending_confirmation"
+            if owner.token_created_at >= Utc::now() - Duration::hours(settings.link_ttl_hours) =>
+        {
+            return Ok(unexpired_link_page());
         }
+        "pending_confirmation" => {}
+        _ => return Ok(invalid_link_page()),
+    }
+    rate_limiter
+        .check(Bucket::ResendByEmail, &owner.email)
+        .await?;
+
+    let subscription_token = generate_subscription_token();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    if !delete_token(&mut transaction, &form.subscription_token)
+        .await
+        .map_err(e500)?
+    {
+        // Another request traded the same link for a new one first.
+        return Ok(invalid_link_page());
+    }
+    store_token(&mut transaction, owner.subscriber_id, &subscription_token)
+        .await
+        .map_err(e500)?;
+    enqueue_confirmation_email(&mut transaction, owner.subscriber_id, &subscription_token)
+        .await
+        .context("Failed to enqueue a confirmation email.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a new confirmation token.")
+        .map_err(e500)?;
+
+    Ok(landing_page(
+        StatusCode::OK,
+        "Check your inbox",
+        &format!(
+            "<p>We sent you a new confirmation link, it is valid for {}.</p>",
+            describe_hours(settings.link_ttl_hours)
+        ),
+    ))
+}
+
+/// E.g. "3 days" rather than "72 hours".
+fn describe_hours(hours: i64) -> String {
+    let (n, unit) = if hours % 24 == 0 {
+        (hours / 24, "day")
+    } else {
+        (hours, "hour")
+    };
+    if n == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{n} {unit}s")
+    }
+}
+
+fn confirmed_page() -> HttpResponse {
+    landing_page(
+        StatusCode::OK,
+        "Subscription confirmed",
+        "<p>Thanks for confirming your subscription - the next newsletter will be in your inbox.</p>",
+    )
+}
+
+fn already_confirmed_page() -> HttpResponse {
+    landing_page(
+        StatusCode::OK,
+        "Already confirmed",
+        "<p>Your subscription is already confirmed, there is nothing else to do.</p>",
+    )
+}
+
+fn expired_link_page(subscription_token: &str) -> HttpResponse {
+    landing_page(
+        StatusCode::GONE,
+        "Link expired",
+        &format!(
+            r#"<p>This confirmation link has expired.</p>
+            <form action="/subscriptions/confirm" method="post">
+                <input type="hidden" name="subscription_token" value="{}">
+                <button type="submit">Send me a new link</button>
+            </form>"#,
+            escape_attribute(subscription_token)
+        ),
+    )
+}
+
+fn unexpired_link_page() -> HttpResponse {
+    landing_page(
+        StatusCode::BAD_REQUEST,
+        "Link still valid",
+        "<p>This confirmation link has not expired, follow it to confirm your subscription.</p>",
+    )
+}
+
+fn invalid_link_page() -> HttpResponse {
+    landing_page(
+        StatusCode::UNAUTHORIZED,
+        "Invalid link",
+        r#"<p>This confirmation link is not valid. <a href="/">Subscribe</a> to our newsletter.</p>"#,
+    )
+}
+
+fn landing_page(status: StatusCode, title: &str, body_html: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>{title}</title>
+            </head>
+            <body>
+                <h1>{title}</h1>
+                {body_html}
+            </body>
+            </html>"#,
+            title = escape_text(title),
+        ))
+}
+
+#[tracing::instrument(name = "Get the subscriber of a token", skip(pool, subscription_token))]
+async fn get_token_owner(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<TokenOwner>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenOwner,
+        r#"
+            SELECT
+                s.id AS subscriber_id,
+                s.email,
+                s.name,
+                s.status,
+                t.created_at AS token_created_at
+            FROM subscription_tokens t
+            JOIN subscriptions s ON s.id = t.subscriber_id
+            WHERE t.subscription_token = $1
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Send a welcome email to a new subscriber",
+    skip_all,
+    fields(subscriber_id = %owner.subscriber_id)
+)]
+async fn send_welcome_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    owner: &TokenOwner,
+    subscription_token: &str,
+    base_url: &str,
+    email_layout: &EmailLayoutSettings,
+    settings: &WelcomeEmailSettings,
+) -> Result<(), anyhow::Error> {
+    if let Some(suppression) = find_suppression(pool, &owner.email)
+        .await
+        .context("Failed to check the suppression list.")?
+    {
+        tracing::info!(
+            suppression.reason = %suppression.reason,
+            "Not sending a welcome email to a suppressed address",
+        );
+        return Ok(());
     }
+
+    let fields = MergeFields::new(&owner.name, base_url, subscription_token);
+    let layout = resolve_layout(pool, None, email_layout)
+        .await
+        .context("Failed to look up the email layout.")?;
+    let html_body = render(&settings.html_body, ContentKind::Html, &fields)
+        .context("Failed to render the HTML body of the welcome email.")?;
+    let html_body = layout
+        .wrap(&html_body, ContentKind::Html, Some(&fields))
+        .context("Failed to render the HTML layout.")?;
+    let text_body = render(&settings.text_body, ContentKind::Text, &fields)
+        .context("Failed to render the text body of the welcome email.")?;
+    let text_body = layout
+        .wrap(&text_body, ContentKind::Text, Some(&fields))
+        .context("Failed to render the text layout.")?;
+    let recipient = SubscriberEmail::parse(&owner.email).map_err(anyhow::Error::msg)?;
+
+    email_client
+        .send_email(&recipient, &settings.subject, &html_body, &text_body, &[])
+        .await?;
+
+    Ok(())
+}
+
+/// Returns `false` if the token was already deleted.
+#[tracing::instrument(name = "Delete a subscription token", skip_all)]
+async fn delete_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+            DELETE FROM subscription_tokens
+            WHERE subscription_token = $1
+        "#,
+        subscription_token
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows == 1)
 }
 
+/// Returns `false` if the subscriber was no longer pending confirmation.
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(subscriber_id: Uuid, pool: &PgPool) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+pub async fn confirm_subscriber(subscriber_id: Uuid, pool: &PgPool) -> Result<bool, sqlx::Error> {
+    let n_updated_rows = sqlx::query!(
         r#"
             UPDATE subscriptions 
             SET status = 'confirmed' 
-            WHERE id = $1
+            WHERE id = $1 AND status = 'pending_confirmation'
         "#,
         subscriber_id
     )
@@ -38,9 +359,10 @@ pub async fn confirm_subscriber(subscriber_id: Uuid, pool: &PgPool) -> Result<()
     .map_err(|e| {
         tracing::error!("Failed to execute query: {e:?}");
         e
-    })?;
+    })?
+    .rows_affected();
 
-    Ok(())
+    Ok(n_updated_rows == 1)
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
@@ -64,3 +386,16 @@ pub async fn get_subscriber_id_from_token(
     })?;
     Ok(result.map(|r| r.subscriber_id))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::describe_hours;
+
+    #[test]
+    fn link_lifetimes_are_described_in_days_when_possible() {
+        assert_eq!(describe_hours(72), "3 days");
+        assert_eq!(describe_hours(24), "1 day");
+        assert_eq!(describe_hours(1), "1 hour");
+        assert_eq!(describe_hours(36), "36 hours");
+    }
+}
@@ -0,0 +1,70 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::subscriptions_confirm::get_subscriber_id_from_token;
+use crate::{
+    html::{escape_attribute, escape_text},
+    utils::e500,
+};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+#[tracing::instrument(name = "Show subscription preferences", skip(parameters, pool))]
+pub async fn subscription_preferences(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+    let subscriber = sqlx::query!(
+        r#"
+            SELECT email, name, status
+            FROM subscriptions
+            WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&**pool)
+    .await
+    .context("Failed to retrieve the subscriber details.")
+    .map_err(e500)?;
+
+    let action_html = if subscriber.status == "confirmed" {
+        format!(
+            r#"<p><a href="/subscriptions/unsubscribe?subscription_token={}">Unsubscribe</a></p>"#,
+            escape_attribute(urlencoding::encode(&parameters.subscription_token))
+        )
+    } else {
+        String::new()
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Subscription preferences</title>
+            </head>
+            <body>
+                <p>Name: {}</p>
+                <p>Email: {}</p>
+                <p>Status: {}</p>
+                {action_html}
+            </body>
+            </html>"#,
+            escape_text(&subscriber.name),
+            escape_text(&subscriber.email),
+            escape_text(&subscriber.status).replace('_', " "),
+        )))
+}
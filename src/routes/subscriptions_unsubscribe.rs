@@ -0,0 +1,129 @@
+use actix_web::{HttpResponse, http::header::ContentType, web};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::subscriptions_confirm::get_subscriber_id_from_token;
+use crate::{
+    domain::{SuppressionReason, SuppressionTarget},
+    html::escape_attribute,
+    suppression::insert_suppression,
+    utils::e500,
+};
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+/// Ask for confirmation rather than unsubscribing straight away: mail scanners and link
+/// prefetchers follow the links in emails, they must not unsubscribe anyone.
+#[tracing::instrument(name = "Show the unsubscribe form", skip(parameters, pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Unsubscribe</title>
+            </head>
+            <body>
+                <p>Do you want to stop receiving our newsletters?</p>
+                <form action="/subscriptions/unsubscribe" method="post">
+                    <input type="hidden" name="subscription_token" value="{}">
+                    <button type="submit">Unsubscribe</button>
+                </form>
+            </body>
+            </html>"#,
+            escape_attribute(&parameters.subscription_token),
+        )))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool))]
+pub async fn unsubscribe(
+    parameters: web::Form<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let email = mark_subscriber_as_unsubscribed(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")
+        .map_err(e500)?;
+    // Keep the address suppressed even if it somehow ends up subscribed again.
+    if let Ok(target) = SuppressionTarget::parse(&email) {
+        insert_suppression(
+            &mut *transaction,
+            &target,
+            SuppressionReason::Unsubscribe,
+            None,
+        )
+        .await
+        .context("Failed to add the address to the suppression list.")
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+            <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Unsubscribed</title>
+            </head>
+            <body>
+                <p>You have been unsubscribed - you will not receive any more newsletters from us.</p>
+            </body>
+            </html>"#,
+        ))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(transaction))]
+async fn mark_subscriber_as_unsubscribed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+            UPDATE subscriptions
+            SET status = 'unsubscribed'
+            WHERE id = $1
+            RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    Ok(row.email)
+}
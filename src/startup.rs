@@ -7,16 +7,30 @@ use actix_web::middleware::from_fn;
 use actix_web::{App, HttpServer, dev::Server, web};
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_flash_messages::storage::CookieMessageStore;
-use secrecy::{ExposeSecret, SecretString};
+use redis::aio::ConnectionManager;
+use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
 use tracing_actix_web::TracingLogger;
 
 use crate::authentication::reject_anonymous_users;
 use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
+use crate::form_token::FormTokens;
+use crate::idempotency::enforce_idempotency;
+use crate::metrics::record_http_metrics;
+use crate::rate_limit::{
+    RateLimiter, limit_confirmations_by_ip, limit_logins_by_ip, limit_subscriptions_by_ip,
+};
+use crate::request_id::{RequestIdRootSpanBuilder, request_id_middleware};
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out, login, login_form, publish_newsletter, publish_newsletter_form, subscribe
+    add_to_suppression_list, admin_dashboard, archive, archived_issue, atom_feed, attachments,
+    audit_log, cancel_newsletter_issue, change_password, change_password_form, confirm,
+    confirm_replayed_publication, create_email_layout, delete_email_layout,
+    delete_uploaded_attachment, email_layouts, export_audit_log, health_check, home, log_out,
+    login, login_form, metrics, newsletter_issues, pause_newsletter_issue, preview_newsletter,
+    publish_newsletter, publish_newsletter_form, ready, remove_from_suppression_list,
+    resend_confirmation, resume_newsletter_issue, rss_feed, subscribe, subscription_preferences,
+    suppression_list, unsubscribe, unsubscribe_form, upload_attachment, upload_config,
 };
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
@@ -34,22 +48,6 @@ impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration
-                .email_client
-                .base_url
-                .parse()
-                .expect("Invalid email client base URL"),
-            sender_email,
-            configuration.email_client.authorization_token,
-            timeout,
-        );
-
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -57,15 +55,7 @@ impl Application {
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
 
-        let server = run(
-            listener,
-            connection_pool,
-            email_client,
-            configuration.application.base_url,
-            configuration.hmac_secret,
-            configuration.redis_uri,
-        )
-        .await?;
+        let server = run(listener, connection_pool, configuration).await?;
 
         Ok(Self { port, server })
     }
@@ -84,18 +74,49 @@ pub struct ApplicationBaseUrl(pub String);
 pub async fn run(
     listener: TcpListener,
     connection_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-    hmac_secret: SecretString,
-    redis_uri: SecretString,
+    configuration: Settings,
 ) -> Result<Server, anyhow::Error> {
+    let Settings {
+        application,
+        email_client,
+        hmac_secret,
+        redis_uri,
+        email_layout,
+        html_sanitizer,
+        readiness,
+        rate_limit,
+        bot_protection,
+        idempotency,
+        attachments: attachment_settings,
+        feed,
+        confirmation,
+        metrics: metrics_settings,
+        ..
+    } = configuration;
     let connection = web::Data::new(connection_pool);
-    let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let email_client = web::Data::new(email_client.client()?);
+    let base_url = web::Data::new(ApplicationBaseUrl(application.base_url));
+    let email_layout = web::Data::new(email_layout);
+    let html_sanitizer = web::Data::new(html_sanitizer);
+    let readiness = web::Data::new(readiness);
+    let idempotency = web::Data::new(idempotency);
+    let attachment_upload = upload_config(&attachment_settings);
+    let attachment_settings = web::Data::new(attachment_settings);
+    let feed = web::Data::new(feed);
+    let confirmation = web::Data::new(confirmation);
+    let metrics_settings = web::Data::new(metrics_settings);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let redis_connection =
+        ConnectionManager::new(redis::Client::open(redis_uri.expose_secret())?).await?;
+    let rate_limiter = web::Data::new(RateLimiter::new(redis_connection.clone(), rate_limit));
+    let form_tokens = web::Data::new(
+        FormTokens::new(hmac_secret.clone(), bot_protection)
+            .with_replay_protection(redis_connection.clone()),
+    );
+    let redis_connection = web::Data::new(redis_connection);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
@@ -103,13 +124,43 @@ pub async fn run(
                 redis_store.clone(),
                 secret_key.clone(),
             ))
-            .wrap(TracingLogger::default())
+            .wrap(from_fn(record_http_metrics))
+            .wrap(from_fn(request_id_middleware))
+            .wrap(TracingLogger::<RequestIdRootSpanBuilder>::new())
             .route("/", web::get().to(home))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route("/feed.xml", web::get().to(atom_feed))
+            .route("/rss.xml", web::get().to(rss_feed))
             .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
+            .route(
+                "/login",
+                web::post().to(login).wrap(from_fn(limit_logins_by_ip)),
+            )
             .route("/health_check", web::get().to(health_check))
-            .route("/subscribe", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/metrics", web::get().to(metrics))
+            .route("/ready", web::get().to(ready))
+            .service(
+                web::resource("/subscribe")
+                    .wrap(from_fn(enforce_idempotency))
+                    .wrap(from_fn(limit_subscriptions_by_ip))
+                    .route(web::post().to(subscribe)),
+            )
+            .service(
+                web::resource("/subscriptions/confirm")
+                    .wrap(from_fn(limit_confirmations_by_ip))
+                    .route(web::get().to(confirm))
+                    .route(web::post().to(resend_confirmation)),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(unsubscribe_form),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(subscription_preferences),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
@@ -118,11 +169,53 @@ pub async fn run(
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out))
                     .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(from_fn(enforce_idempotency))
+                            .wrap(from_fn(confirm_replayed_publication))
+                            .route(web::post().to(publish_newsletter)),
+                    )
+                    .route("/newsletters/preview", web::post().to(preview_newsletter))
+                    .route("/issues", web::get().to(newsletter_issues))
+                    .route("/issues/pause", web::post().to(pause_newsletter_issue))
+                    .route("/issues/resume", web::post().to(resume_newsletter_issue))
+                    .route("/issues/cancel", web::post().to(cancel_newsletter_issue))
+                    .route("/suppressions", web::get().to(suppression_list))
+                    .route("/suppressions", web::post().to(add_to_suppression_list))
+                    .route(
+                        "/suppressions/remove",
+                        web::post().to(remove_from_suppression_list),
+                    )
+                    .route("/layouts", web::get().to(email_layouts))
+                    .route("/layouts", web::post().to(create_email_layout))
+                    .route("/layouts/delete", web::post().to(delete_email_layout))
+                    .route("/attachments", web::get().to(attachments))
+                    .service(
+                        web::resource("/attachments")
+                            .app_data(attachment_upload.clone())
+                            .route(web::post().to(upload_attachment)),
+                    )
+                    .route(
+                        "/attachments/delete",
+                        web::post().to(delete_uploaded_attachment),
+                    )
+                    .route("/audit", web::get().to(audit_log))
+                    .route("/audit/export", web::get().to(export_audit_log)),
             )
             .app_data(connection.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(email_layout.clone())
+            .app_data(html_sanitizer.clone())
+            .app_data(readiness.clone())
+            .app_data(idempotency.clone())
+            .app_data(attachment_settings.clone())
+            .app_data(feed.clone())
+            .app_data(confirmation.clone())
+            .app_data(metrics_settings.clone())
+            .app_data(redis_connection.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(form_tokens.clone())
             .app_data(hmac_secret.clone())
     })
     .listen(listener)?
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::{SuppressionReason, SuppressionTarget};
+
+pub struct Suppression {
+    pub suppression_id: Uuid,
+    pub kind: String,
+    pub value: String,
+    pub reason: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Return the suppression entry matching `email`, either by address or by domain.
+#[tracing::instrument(name = "Look up email in the suppression list", skip(executor, email))]
+pub async fn find_suppression(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+            SELECT suppression_id, kind, value, reason, note, created_at
+            FROM suppressions
+            WHERE
+                (kind = 'email' AND value = lower($1)) OR
+                (kind = 'domain' AND value = lower(split_part($1, '@', 2)))
+            ORDER BY kind DESC
+            LIMIT 1
+        "#,
+        email
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[tracing::instrument(name = "List suppression entries", skip(pool))]
+pub async fn get_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+            SELECT suppression_id, kind, value, reason, note, created_at
+            FROM suppressions
+            ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `false` if the target was already on the suppression list.
+#[tracing::instrument(
+    name = "Add entry to the suppression list",
+    skip(executor, target),
+    fields(kind = target.kind())
+)]
+pub async fn insert_suppression(
+    executor: impl PgExecutor<'_>,
+    target: &SuppressionTarget,
+    reason: SuppressionReason,
+    note: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+            INSERT INTO suppressions (
+                suppression_id,
+                kind,
+                value,
+                reason,
+                note,
+                created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, now())
+            ON CONFLICT (kind, value) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        target.kind(),
+        target.value(),
+        reason.as_str(),
+        note
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(n_inserted_rows > 0)
+}
+
+/// Returns `false` if there was no entry with the given id.
+#[tracing::instrument(name = "Remove entry from the suppression list", skip(executor))]
+pub async fn delete_suppression(
+    executor: impl PgExecutor<'_>,
+    suppression_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_deleted_rows = sqlx::query!(
+        r#"
+            DELETE FROM suppressions
+            WHERE suppression_id = $1
+        "#,
+        suppression_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(n_deleted_rows > 0)
+}
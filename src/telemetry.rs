@@ -1,4 +1,20 @@
+use once_cell::sync::OnceCell;
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector, TextMapPropagator},
+    trace::{SpanContext, TraceContextExt, TracerProvider as _},
+};
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    trace::{SdkTracerProvider, Tracer},
+};
 use tokio::task::JoinHandle;
+use tracing::Subscriber;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+static TRACER_PROVIDER: OnceCell<SdkTracerProvider> = OnceCell::new();
 
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
@@ -8,3 +24,135 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+/// Turn `tracing` spans into OpenTelemetry spans.
+///
+/// They are exported over OTLP/HTTP if `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set. Otherwise they are not exported, but still
+/// get trace ids, which are propagated to the delivery worker and to the email API.
+pub fn opentelemetry_layer<S>(service_name: String) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let mut builder = SdkTracerProvider::builder().with_resource(
+        Resource::builder()
+            .with_service_name(service_name.clone())
+            .build(),
+    );
+    let exporter_configured = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|var| std::env::var_os(var).is_some());
+    if exporter_configured {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()
+            .expect("Failed to build the OTLP span exporter");
+        builder = builder.with_batch_exporter(exporter);
+    }
+    let provider = builder.build();
+    let tracer = provider.tracer(service_name);
+    global::set_tracer_provider(provider.clone());
+    let _ = TRACER_PROVIDER.set(provider);
+
+    tracing_opentelemetry::layer().with_tracer(tracer)
+}
+
+/// Export the spans that are still buffered. To be called before the process exits.
+pub fn shutdown_tracer_provider() {
+    if let Some(provider) = TRACER_PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to shut down the tracer provider"
+        );
+    }
+}
+
+/// The W3C trace context of a span, in a form that can be stored next to queued work
+/// or sent as HTTP headers.
+#[derive(Debug, Default, Clone)]
+pub struct TraceContext {
+    pub traceparent: Option<String>,
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    pub fn current() -> Self {
+        let mut trace_context = Self::default();
+        TraceContextPropagator::new()
+            .inject_context(&tracing::Span::current().context(), &mut trace_context);
+        trace_context
+    }
+
+    /// The span the context was captured from - invalid if there was none.
+    pub fn span_context(&self) -> SpanContext {
+        TraceContextPropagator::new()
+            .extract(self)
+            .span()
+            .span_context()
+            .clone()
+    }
+
+    /// A context whose active span is the one the trace context was captured from, to
+    /// make it the parent of a span that runs on its behalf.
+    pub fn parent_context(&self) -> opentelemetry::Context {
+        TraceContextPropagator::new().extract(self)
+    }
+}
+
+impl Injector for TraceContext {
+    fn set(&mut self, key: &str, value: String) {
+        let value = Some(value).filter(|v| !v.is_empty());
+        match key {
+            "traceparent" => self.traceparent = value,
+            "tracestate" => self.tracestate = value,
+            _ => {}
+        }
+    }
+}
+
+impl Extractor for TraceContext {
+    fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "traceparent" => self.traceparent.as_deref(),
+            "tracestate" => self.tracestate.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        vec!["traceparent", "tracestate"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraceContext;
+
+    #[test]
+    fn a_stored_trace_context_points_back_to_its_span() {
+        let trace_context = TraceContext {
+            traceparent: Some("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".into()),
+            tracestate: None,
+        };
+        let span_context = trace_context.span_context();
+        assert!(span_context.is_valid());
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+    }
+
+    #[test]
+    fn a_missing_trace_context_is_invalid() {
+        assert!(!TraceContext::default().span_context().is_valid());
+    }
+}
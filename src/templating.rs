@@ -0,0 +1,250 @@
+use minijinja::{AutoEscape, Environment, UndefinedBehavior, Value, context};
+
+/// Which body of an email a template is rendered into.
+#[derive(Debug, Clone, Copy)]
+pub enum ContentKind {
+    Html,
+    Text,
+}
+
+/// Per-recipient values available to newsletter templates, e.g. `{{ name }}`.
+pub struct MergeFields {
+    pub name: String,
+    pub unsubscribe_url: String,
+    pub preferences_url: String,
+    /// Set for issues published to the public archive.
+    pub view_in_browser_url: Option<String>,
+}
+
+impl MergeFields {
+    pub fn new(name: &str, base_url: &str, subscription_token: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            unsubscribe_url: format!(
+                "{base_url}/subscriptions/unsubscribe?subscription_token={subscription_token}"
+            ),
+            preferences_url: format!(
+                "{base_url}/subscriptions/preferences?subscription_token={subscription_token}"
+            ),
+            view_in_browser_url: None,
+        }
+    }
+
+    /// Stand-in values, used to validate and preview templates.
+    pub fn example() -> Self {
+        Self {
+            view_in_browser_url: Some("https://example.com/archive/example".into()),
+            ..Self::new("Ursula Le Guin", "https://example.com", "token")
+        }
+    }
+
+    /// Values for readers of the issue at `slug` in the public archive, whose subscription
+    /// links lead to the home page.
+    pub fn public(base_url: &str, slug: &str) -> Self {
+        Self {
+            name: "reader".into(),
+            unsubscribe_url: format!("{base_url}/"),
+            preferences_url: format!("{base_url}/"),
+            view_in_browser_url: Some(format!("{base_url}/archive/{slug}")),
+        }
+    }
+}
+
+fn environment(kind: ContentKind) -> Environment<'static> {
+    let mut env = Environment::new();
+    // Typos such as `{{ nmae }}` must fail instead of silently rendering nothing.
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_auto_escape_callback(move |_| match kind {
+        ContentKind::Html => AutoEscape::Html,
+        ContentKind::Text => AutoEscape::None,
+    });
+    env
+}
+
+pub fn render(
+    source: &str,
+    kind: ContentKind,
+    fields: &MergeFields,
+) -> Result<String, minijinja::Error> {
+    // The links are built from our own base URL and an alphanumeric token,
+    // they must not be HTML-escaped or `href` attributes would break.
+    let ctx = context! {
+        name => &fields.name,
+        unsubscribe_url => Value::from_safe_string(fields.unsubscribe_url.clone()),
+        preferences_url => Value::from_safe_string(fields.preferences_url.clone()),
+        view_in_browser_url => fields.view_in_browser_url.clone().map(Value::from_safe_string),
+    };
+    environment(kind).render_str(source, ctx)
+}
+
+/// Render `source` against example values to catch syntax errors and unknown placeholders
+/// before anything is queued for delivery.
+pub fn validate(source: &str, kind: ContentKind) -> Result<(), minijinja::Error> {
+    render(source, kind, &MergeFields::example()).map(|_| ())
+}
+
+/// Whether `source` uses `{{ unsubscribe_url }}`: each newsletter issue must link to it,
+/// either from its content or from its layout.
+pub fn links_to_unsubscribe(source: &str, kind: ContentKind) -> bool {
+    uses_variable(source, kind, "unsubscribe_url")
+}
+
+/// Whether `source` uses `{{ view_in_browser_url }}`, which is only set for issues
+/// published to the public archive.
+pub fn links_to_archive(source: &str, kind: ContentKind) -> bool {
+    uses_variable(source, kind, "view_in_browser_url")
+}
+
+fn uses_variable(source: &str, kind: ContentKind, name: &str) -> bool {
+    environment(kind)
+        .template_from_str(source)
+        .is_ok_and(|t| t.undeclared_variables(false).contains(name))
+}
+
+/// Wrap an already-rendered email body in a layout template.
+///
+/// Besides `{{ content }}` and `{{ physical_address }}`, layouts see the recipient's merge
+/// fields - they are `none` for emails sent before someone subscribes, e.g. confirmations.
+pub fn render_layout(
+    source: &str,
+    kind: ContentKind,
+    content: &str,
+    physical_address: &str,
+    fields: Option<&MergeFields>,
+) -> Result<String, minijinja::Error> {
+    let ctx = context! {
+        content => Value::from_safe_string(content.to_owned()),
+        physical_address => physical_address,
+        name => fields.map(|f| f.name.as_str()),
+        unsubscribe_url => fields.map(|f| Value::from_safe_string(f.unsubscribe_url.clone())),
+        preferences_url => fields.map(|f| Value::from_safe_string(f.preferences_url.clone())),
+        view_in_browser_url => fields
+            .and_then(|f| f.view_in_browser_url.clone())
+            .map(Value::from_safe_string),
+    };
+    environment(kind).render_str(source, ctx)
+}
+
+/// Render a layout both with and without merge fields, and check that it actually
+/// includes the email body.
+pub fn validate_layout(source: &str, kind: ContentKind) -> Result<(), String> {
+    const MARKER: &str = "ZLAYOUTCONTENTZ";
+    for fields in [Some(&MergeFields::example()), None] {
+        let rendered = render_layout(source, kind, MARKER, "1 Example Street", fields)
+            .map_err(|e| e.to_string())?;
+        if !rendered.contains(MARKER) {
+            return Err("The layout must include the `{{ content }}` placeholder.".into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::{
+        ContentKind, MergeFields, links_to_unsubscribe, render, render_layout, validate,
+        validate_layout,
+    };
+
+    fn fields() -> MergeFields {
+        MergeFields::new("<Ursula>", "http://127.0.0.1", "abc")
+    }
+
+    #[test]
+    fn merge_fields_are_rendered() {
+        let rendered = render(
+            "Hi {{ name }}! {{ unsubscribe_url }} {{ preferences_url }}",
+            ContentKind::Text,
+            &fields(),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "Hi <Ursula>! \
+            http://127.0.0.1/subscriptions/unsubscribe?subscription_token=abc \
+            http://127.0.0.1/subscriptions/preferences?subscription_token=abc"
+        );
+    }
+
+    #[test]
+    fn html_templates_escape_merge_fields() {
+        let rendered = render("<p>Hi {{ name }}</p>", ContentKind::Html, &fields()).unwrap();
+        assert_eq!(rendered, "<p>Hi &lt;Ursula&gt;</p>");
+    }
+
+    #[test]
+    fn content_without_placeholders_is_valid() {
+        assert_ok!(validate(
+            "<p>Newsletter body as HTML</p>",
+            ContentKind::Html
+        ));
+    }
+
+    #[test]
+    fn syntax_errors_are_rejected() {
+        assert_err!(validate("Hi {{ name", ContentKind::Text));
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        assert_err!(validate("Hi {{ nmae }}", ContentKind::Text));
+    }
+
+    #[test]
+    fn layouts_wrap_content_without_escaping_it() {
+        let rendered = render_layout(
+            "<body>{{ content }}<p>{{ physical_address }}</p></body>",
+            ContentKind::Html,
+            "<h1>Hi</h1>",
+            "1 Main St & Co",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            "<body><h1>Hi</h1><p>1 Main St &amp; Co</p></body>"
+        );
+    }
+
+    #[test]
+    fn layouts_can_check_for_merge_fields() {
+        let source = "{{ content }}{% if unsubscribe_url %} {{ unsubscribe_url }}{% endif %}";
+        let with_fields =
+            render_layout(source, ContentKind::Text, "Body", "", Some(&fields())).unwrap();
+        let without_fields = render_layout(source, ContentKind::Text, "Body", "", None).unwrap();
+        assert_eq!(
+            with_fields,
+            "Body http://127.0.0.1/subscriptions/unsubscribe?subscription_token=abc"
+        );
+        assert_eq!(without_fields, "Body");
+    }
+
+    #[test]
+    fn layouts_without_content_are_rejected() {
+        assert_err!(validate_layout("<p>Footer only</p>", ContentKind::Html));
+    }
+
+    #[test]
+    fn layouts_with_unknown_placeholders_are_rejected() {
+        assert_err!(validate_layout(
+            "{{ content }} {{ adress }}",
+            ContentKind::Text
+        ));
+    }
+
+    #[test]
+    fn unsubscribe_links_are_detected_wherever_they_are_used() {
+        for source in [
+            "Unsubscribe: {{ unsubscribe_url }}",
+            "<a href=\"{{unsubscribe_url}}\">Unsubscribe</a>",
+            "{% if unsubscribe_url %}{{ unsubscribe_url }}{% endif %}",
+        ] {
+            assert!(links_to_unsubscribe(source, ContentKind::Html), "{source}");
+        }
+        for source in ["Hi {{ name }}", "unsubscribe_url", "{{ preferences_url }}"] {
+            assert!(!links_to_unsubscribe(source, ContentKind::Html), "{source}");
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{TestUser, assert_is_redirect_to, spawn_app};
 
 #[tokio::test]
 async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
@@ -35,3 +35,22 @@ async fn logout_clears_session_state() {
     let response = test_app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
 }
+
+#[tokio::test]
+async fn the_username_is_escaped_on_the_dashboard() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let test_user = TestUser {
+        username: "<script>alert('boom')</script>".into(),
+        ..TestUser::generate()
+    };
+    test_user.store(&test_app.db_pool).await;
+
+    // Act
+    test_user.login(&test_app).await;
+    let html_page = test_app.get_admin_dashboard_html().await;
+
+    // Assert
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("Welcome &lt;script&gt;alert('boom')&lt;/script&gt;!"));
+}
@@ -0,0 +1,227 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, UrlEncodable, assert_is_redirect_to, fake_name, spawn_app};
+
+async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        "ursula@example.com".url_encode()
+    );
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Publish an issue and return its slug.
+async fn publish_newsletter(test_app: &TestApp, title: &str, archived: bool) -> String {
+    let mut body = serde_json::json!({
+        "title": title,
+        "text_content": "Hi {{ name }}, here is the news.",
+        "html_content": "<p>Hi {{ name }}, here is the news.</p><script>alert(1)</script>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    if archived {
+        body["publish_to_archive"] = "on".into();
+    }
+    let response = test_app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!("SELECT slug FROM newsletter_issues WHERE title = $1", title)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .slug
+}
+
+#[tokio::test]
+async fn archived_issues_are_public() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let slug = publish_newsletter(&test_app, "Issue #1: Hello", true).await;
+    test_app.post_logout().await;
+
+    // Act
+    let archive_page = test_app.get_archive_html().await;
+    let response = test_app.get_archived_issue(&slug).await;
+
+    // Assert
+    assert!(slug.starts_with("issue-1-hello-"));
+    assert!(archive_page.contains(&format!(r#"<a href="/archive/{slug}">Issue #1: Hello</a>"#)));
+    assert_eq!(response.status().as_u16(), 200);
+    let issue_page = response.text().await.unwrap();
+    assert!(issue_page.contains("<p>Hi reader, here is the news.</p>"));
+    assert!(!issue_page.contains("<script>"));
+    assert!(!issue_page.contains("subscription_token"));
+}
+
+#[tokio::test]
+async fn issues_are_not_archived_unless_requested() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let slug = publish_newsletter(&test_app, "Private issue", false).await;
+
+    // Act
+    let archive_page = test_app.get_archive_html().await;
+    let response = test_app.get_archived_issue(&slug).await;
+
+    // Assert
+    assert!(!archive_page.contains("Private issue"));
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_removed_from_the_archive() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let slug = publish_newsletter(&test_app, "Cancelled issue", true).await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    let response = test_app
+        .post_issue_action(
+            "cancel",
+            &serde_json::json!({ "newsletter_issue_id": issue_id }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+
+    // Assert
+    let response = test_app.get_archived_issue(&slug).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn archived_issues_link_to_their_archive_page() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let slug = publish_newsletter(&test_app, "Archived issue", true).await;
+    publish_newsletter(&test_app, "Private issue", false).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let view_in_browser_url = format!("{}/archive/{slug}", test_app.address);
+    for request in test_app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let html_body = body["HtmlBody"].as_str().unwrap();
+        let text_body = body["TextBody"].as_str().unwrap();
+        let archived = body["Subject"] == "Archived issue";
+        assert_eq!(
+            html_body.contains(&format!(
+                r#"<a href="{view_in_browser_url}">View in browser</a>"#
+            )),
+            archived
+        );
+        assert_eq!(
+            text_body.contains(&format!("View in browser: {view_in_browser_url}")),
+            archived
+        );
+    }
+}
+
+fn body_linking_to_the_archive(title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": "View in browser: {{ view_in_browser_url }}",
+        "html_content": r#"<p><a href="{{ view_in_browser_url }}">View in browser</a></p>"#,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    })
+}
+
+#[tokio::test]
+async fn issues_not_published_to_the_archive_cannot_link_to_it() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&body_linking_to_the_archive("Private issue"))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = test_app.get_publish_newsletter_html().await;
+    assert!(
+        html_page.contains(
+            "Only issues published to the archive can link to {{ view_in_browser_url }}."
+        )
+    );
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn archived_issues_linking_to_the_archive_link_to_their_own_page() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let mut body = body_linking_to_the_archive("Archived issue");
+    body["publish_to_archive"] = "on".into();
+    let response = test_app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+
+    // Act
+    let html_page = test_app
+        .get_archived_issue(&slug)
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains(&format!(
+        r#"/archive/{slug}" rel="noopener noreferrer">View in browser</a>"#
+    )));
+}
@@ -0,0 +1,314 @@
+use base64::Engine;
+use reqwest::multipart::{Form, Part};
+use uuid::Uuid;
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, UrlEncodable, assert_is_redirect_to, fake_name, spawn_app};
+
+async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        "ursula@example.com".url_encode()
+    );
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn upload_form(file_name: &str, content_type: &str, content: Vec<u8>, inline: bool) -> Form {
+    let file = Part::bytes(content)
+        .file_name(file_name.to_owned())
+        .mime_str(content_type)
+        .unwrap();
+    let form = Form::new().part("file", file);
+    if inline {
+        form.text("inline", "on")
+    } else {
+        form
+    }
+}
+
+async fn upload_attachment(
+    test_app: &TestApp,
+    file_name: &str,
+    content_type: &str,
+    content: &[u8],
+    inline: bool,
+) -> Uuid {
+    let response = test_app
+        .post_upload_attachment(upload_form(
+            file_name,
+            content_type,
+            content.to_vec(),
+            inline,
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/admin/attachments");
+    sqlx::query!(
+        "SELECT attachment_id FROM attachments WHERE file_name = $1",
+        file_name
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .attachment_id
+}
+
+async fn count_attachments(test_app: &TestApp) -> i64 {
+    sqlx::query!("SELECT count(*) AS \"count!\" FROM attachments")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+fn publish_body(attachment_ids: &[Uuid]) -> Vec<(&'static str, String)> {
+    let mut body = vec![
+        ("title", "Newsletter title".to_string()),
+        ("text_content", "Newsletter body as plain text".to_string()),
+        ("html_content", "<p>Newsletter body as HTML</p>".to_string()),
+        ("idempotency_key", Uuid::new_v4().to_string()),
+    ];
+    for id in attachment_ids {
+        body.push(("attachment_id", id.to_string()));
+    }
+    body
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_upload_attachments() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_upload_attachment(upload_form(
+            "report.pdf",
+            "application/pdf",
+            b"%PDF".to_vec(),
+            false,
+        ))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(count_attachments(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn uploaded_attachments_are_listed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let attachment_id = upload_attachment(&test_app, "logo.png", "image/png", b"PNG", true).await;
+
+    // Assert
+    let html_page = test_app.get_attachments_html().await;
+    assert!(html_page.contains("The attachment has been uploaded."));
+    assert!(html_page.contains("logo.png"));
+    assert!(html_page.contains(&format!("cid:{attachment_id}")));
+}
+
+#[tokio::test]
+async fn attachments_and_inline_images_are_sent_with_the_issue() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let report_id = upload_attachment(
+        &test_app,
+        "report.pdf",
+        "application/pdf",
+        b"%PDF-1.7",
+        false,
+    )
+    .await;
+    let logo_id = upload_attachment(&test_app, "logo.png", "image/png", b"PNG", true).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&publish_body(&[report_id, logo_id, report_id]))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let base64 = base64::engine::general_purpose::STANDARD;
+    assert_eq!(
+        body["Attachments"],
+        serde_json::json!([
+            {
+                "Name": "report.pdf",
+                "Content": base64.encode(b"%PDF-1.7"),
+                "ContentType": "application/pdf",
+            },
+            {
+                "Name": "logo.png",
+                "Content": base64.encode(b"PNG"),
+                "ContentType": "image/png",
+                "ContentID": format!("cid:{logo_id}"),
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn files_over_the_size_limit_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    for size in [10_001, 50_000] {
+        // Act
+        let response = test_app
+            .post_upload_attachment(upload_form(
+                "huge.bin",
+                "application/octet-stream",
+                vec![0; size],
+                false,
+            ))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/attachments");
+        let html_page = test_app.get_attachments_html().await;
+        assert!(html_page.contains("The file is too large"));
+        assert_eq!(count_attachments(&test_app).await, 0);
+    }
+}
+
+#[tokio::test]
+async fn issues_whose_attachments_exceed_the_total_size_limit_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let first_id = upload_attachment(
+        &test_app,
+        "first.bin",
+        "application/octet-stream",
+        &[0; 8_000],
+        false,
+    )
+    .await;
+    let second_id = upload_attachment(
+        &test_app,
+        "second.bin",
+        "application/octet-stream",
+        &[0; 8_000],
+        false,
+    )
+    .await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&publish_body(&[first_id, second_id]))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = test_app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The selected attachments add up to more than"));
+    let n_issues = sqlx::query!("SELECT count(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn only_images_can_be_shown_inline() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_upload_attachment(upload_form(
+            "report.pdf",
+            "application/pdf",
+            b"%PDF".to_vec(),
+            true,
+        ))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/attachments");
+    let html_page = test_app.get_attachments_html().await;
+    assert!(html_page.contains("Only images can be shown inline."));
+    assert_eq!(count_attachments(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn attachments_sent_with_an_issue_cannot_be_deleted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let unused_id =
+        upload_attachment(&test_app, "unused.pdf", "application/pdf", b"%PDF", false).await;
+    let sent_id = upload_attachment(&test_app, "sent.pdf", "application/pdf", b"%PDF", false).await;
+    let response = test_app
+        .post_publish_newsletter(&publish_body(&[sent_id]))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act
+    for attachment_id in [unused_id, sent_id] {
+        let response = test_app
+            .post_delete_attachment(&serde_json::json!({ "attachment_id": attachment_id }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/attachments");
+    }
+
+    // Assert
+    let remaining = sqlx::query!("SELECT attachment_id FROM attachments")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].attachment_id, sent_id);
+}
@@ -0,0 +1,213 @@
+use uuid::Uuid;
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+struct AuditRow {
+    user_id: Option<Uuid>,
+    action: String,
+    target: Option<String>,
+    ip_address: Option<String>,
+}
+
+async fn audit_rows(test_app: &TestApp) -> Vec<AuditRow> {
+    sqlx::query_as!(
+        AuditRow,
+        "SELECT user_id, action, target, ip_address FROM audit_log ORDER BY created_at"
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn logging_in_and_out_is_audited() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    test_app.test_user.login(&test_app).await;
+    test_app.post_logout().await;
+
+    // Assert
+    let rows = audit_rows(&test_app).await;
+    let actions: Vec<_> = rows.iter().map(|r| r.action.as_str()).collect();
+    assert_eq!(actions, ["login", "logout"]);
+    for row in &rows {
+        assert_eq!(row.user_id, Some(test_app.test_user.user_id));
+        assert_eq!(row.ip_address.as_deref(), Some("127.0.0.1"));
+    }
+}
+
+#[tokio::test]
+async fn failed_logins_are_audited_with_the_attempted_username() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    test_app
+        .post_login(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password"
+        }))
+        .await;
+
+    // Assert
+    let rows = audit_rows(&test_app).await;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].action, "login_failed");
+    assert_eq!(rows[0].user_id, None);
+    assert_eq!(rows[0].target.as_deref(), Some("random-username"));
+}
+
+#[tokio::test]
+async fn forwarded_for_headers_are_not_trusted_by_default() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    test_app
+        .api_client
+        .post(format!("{}/login", &test_app.address))
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password"
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let rows = audit_rows(&test_app).await;
+    assert_eq!(rows[0].ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn changing_the_password_is_audited() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    test_app
+        .post_change_password(&serde_json::json!({
+            "current_password": &test_app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    // Assert
+    let rows = audit_rows(&test_app).await;
+    assert_eq!(rows.last().unwrap().action, "password_changed");
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_is_audited() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let issue_id = sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    let row = audit_rows(&test_app).await.pop().unwrap();
+    assert_eq!(row.action, "newsletter_published");
+    assert_eq!(row.user_id, Some(test_app.test_user.user_id));
+    assert_eq!(row.target, Some(issue_id.to_string()));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_audit_log() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.get_audit_log(&[]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_filtered_by_action() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app
+        .post_login(&serde_json::json!({
+            "username": "intruder",
+            "password": "guess"
+        }))
+        .await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let html = test_app
+        .get_audit_log_html(&[("action", "login_failed")])
+        .await;
+
+    // Assert
+    assert!(html.contains("<td>intruder</td>"));
+    assert!(!html.contains(&format!("<td>{}</td>", test_app.test_user.username)));
+}
+
+#[tokio::test]
+async fn invalid_filters_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    for query in [[("action", "drop_tables")], [("since", "yesterday")]] {
+        // Act
+        let response = test_app.get_audit_log(&query).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[tokio::test]
+async fn the_audit_log_can_be_exported_as_csv() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app
+        .post_login(&serde_json::json!({
+            "username": "=HYPERLINK(\"http://evil.example\")",
+            "password": "guess"
+        }))
+        .await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app.get_audit_log_export(&[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "created_at,username,action,target,ip_address,user_agent"
+    );
+    // Most recent first.
+    assert!(lines[1].contains(&format!(",{},login,", test_app.test_user.username)));
+    assert!(lines[2].contains(r#",login_failed,"'=HYPERLINK(""http://evil.example"")","#));
+}
@@ -0,0 +1,193 @@
+use wiremock::{Mock, ResponseTemplate, matchers::any};
+
+use crate::helpers::{TestApp, spawn_app};
+
+fn rejected(metrics: &str, reason: &str) -> f64 {
+    let prefix = format!(r#"subscriptions_rejected_total{{reason="{reason}"}} "#);
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(&prefix))
+        .map(|value| value.parse().unwrap())
+        .unwrap_or(0.0)
+}
+
+async fn post_subscription(test_app: &TestApp, body: &str) -> reqwest::Response {
+    test_app
+        .api_client
+        .post(format!("{}/subscribe", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body.to_owned())
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn mount_email_api(test_app: &TestApp) {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn the_home_page_serves_a_subscription_form_with_a_valid_token() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let html = test_app
+        .api_client
+        .get(&test_app.address)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html.contains(r#"<form action="/subscribe" method="post">"#));
+    assert!(html.contains(r#"name="website""#));
+    let token = html
+        .split(r#"name="form_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    // Submitted right away, so too fast, but correctly signed
+    assert_eq!(
+        test_app.form_tokens.verify(token).err(),
+        Some(zero2prod::form_token::FormTokenError::TooFast)
+    );
+}
+
+#[tokio::test]
+async fn submissions_without_a_token_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let before = rejected(&test_app.get_metrics_text().await, "missing_token");
+
+    // Act
+    let response =
+        post_subscription(&test_app, "name=le%20guin&email=ursula_le_guin%40gmail.com").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let after = rejected(&test_app.get_metrics_text().await, "missing_token");
+    assert!(after > before);
+}
+
+#[tokio::test]
+async fn submissions_sent_right_after_loading_the_form_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let token = test_app.form_tokens.issue();
+
+    // Act
+    let response = post_subscription(
+        &test_app,
+        &format!("name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={token}"),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn tampered_tokens_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let token = test_app.form_token();
+    let (timestamp, rest) = token.split_once('.').unwrap();
+    let tampered = format!("{}.{rest}", timestamp.parse::<i64>().unwrap() - 3600);
+
+    // Act
+    let response = post_subscription(
+        &test_app,
+        &format!("name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={tampered}"),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn filling_the_honeypot_pretends_to_succeed_without_subscribing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let before = rejected(&test_app.get_metrics_text().await, "honeypot");
+
+    // Act
+    let response = post_subscription(
+        &test_app,
+        &format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&website=spam.example&form_token={}",
+            test_app.form_token()
+        ),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriptions = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscriptions.is_empty());
+    let after = rejected(&test_app.get_metrics_text().await, "honeypot");
+    assert!(after > before);
+}
+
+#[tokio::test]
+async fn a_token_cannot_be_used_twice() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mount_email_api(&test_app).await;
+    let token = test_app.form_token();
+    let first = post_subscription(
+        &test_app,
+        &format!("name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={token}"),
+    )
+    .await;
+    assert_eq!(first.status().as_u16(), 200);
+
+    // Act
+    let replayed = post_subscription(
+        &test_app,
+        &format!("name=someone&email=someone_else%40gmail.com&form_token={token}"),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(replayed.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_token_is_not_used_up_by_an_invalid_submission() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mount_email_api(&test_app).await;
+    let token = test_app.form_token();
+    let invalid = post_subscription(
+        &test_app,
+        &format!("name=le%20guin&email=not-an-email&form_token={token}"),
+    )
+    .await;
+    assert_eq!(invalid.status().as_u16(), 400);
+
+    // Act
+    let corrected = post_subscription(
+        &test_app,
+        &format!("name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={token}"),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(corrected.status().as_u16(), 200);
+}
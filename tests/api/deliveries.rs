@@ -0,0 +1,241 @@
+use wiremock::{
+    Mock, MockBuilder, ResponseTemplate,
+    matchers::{method, path},
+};
+use zero2prod::delivery_log::{get_issue_deliveries, get_subscriber_deliveries};
+
+use crate::helpers::{TestApp, UrlEncodable, assert_is_redirect_to, fake_name, spawn_app};
+
+async fn create_confirmed_subscriber(test_app: &TestApp, email: &str) -> uuid::Uuid {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        email.url_encode()
+    );
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query_scalar!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn publish_newsletter(test_app: &TestApp) -> uuid::Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+fn when_sending_an_email() -> MockBuilder {
+    Mock::given(path("/email")).and(method("POST"))
+}
+
+#[tokio::test]
+async fn successful_deliveries_are_logged_with_the_provider_message_id() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app, "reader@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "reader@example.com",
+            "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&test_app).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = get_issue_deliveries(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    let delivery = &deliveries[0];
+    assert_eq!(delivery.subscriber_id, Some(subscriber_id));
+    assert_eq!(delivery.subscriber_email, "reader@example.com");
+    assert_eq!(delivery.outcome, "sent");
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+    );
+    assert!(delivery.error.is_none());
+    assert!(delivery.completed_at >= delivery.attempted_at);
+
+    let deliveries = get_subscriber_deliveries(&test_app.db_pool, subscriber_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].newsletter_issue_id, issue_id);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_logged_with_the_error() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app, "reader@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&test_app).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = get_issue_deliveries(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].outcome, "failed");
+    assert!(deliveries[0].provider_message_id.is_none());
+    assert!(deliveries[0].error.as_deref().unwrap().contains("500"));
+}
+
+#[tokio::test]
+async fn recipients_suppressed_after_publication_are_logged_as_suppressed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app, "reader@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&test_app).await;
+    let response = test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "reader@example.com",
+            "reason": "bounce",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = get_subscriber_deliveries(&test_app.db_pool, subscriber_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].newsletter_issue_id, issue_id);
+    assert_eq!(deliveries[0].outcome, "suppressed");
+}
+
+#[tokio::test]
+async fn issues_are_delivered_to_the_current_address_of_the_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app, "old@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&test_app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET email = 'new@example.com' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "new@example.com");
+    let deliveries = get_issue_deliveries(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries[0].subscriber_email, "new@example.com");
+}
+
+#[tokio::test]
+async fn recipients_no_longer_confirmed_at_send_time_are_skipped() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app, "reader@example.com").await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&test_app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = get_issue_deliveries(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].outcome, "skipped");
+}
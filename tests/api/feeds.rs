@@ -0,0 +1,168 @@
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+async fn publish_newsletter(test_app: &TestApp, title: &str, archived: bool) {
+    let mut body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text {{ unsubscribe_url }}",
+        "html_content": "<p>Hi {{ name }} &amp; welcome</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    if archived {
+        body["publish_to_archive"] = "on".into();
+    }
+    let response = test_app.post_publish_newsletter(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn the_atom_feed_lists_archived_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    publish_newsletter(&test_app, "Tips & tricks", true).await;
+    publish_newsletter(&test_app, "Private issue", false).await;
+
+    // Act
+    let response = test_app.get_feed("feed.xml", &[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>Tips &amp; tricks</title>"));
+    assert!(feed.contains("&lt;p&gt;Hi reader &amp;amp; welcome&lt;/p&gt;"));
+    assert!(!feed.contains("Private issue"));
+    assert_eq!(feed.matches("<entry>").count(), 1);
+}
+
+#[tokio::test]
+async fn the_rss_feed_lists_archived_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    publish_newsletter(&test_app, "Tips & tricks", true).await;
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+
+    // Act
+    let response = test_app.get_feed("rss.xml", &[]).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("<title>Tips &amp; tricks</title>"));
+    assert!(feed.contains(&format!("/archive/{slug}</link>")));
+    assert!(
+        feed.contains("<description>&lt;p&gt;Hi reader &amp;amp; welcome&lt;/p&gt;</description>")
+    );
+}
+
+#[tokio::test]
+async fn an_empty_feed_is_served() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    for feed in ["feed.xml", "rss.xml"] {
+        // Act
+        let response = test_app.get_feed(feed, &[]).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers().get("Last-Modified").is_none());
+        let feed = response.text().await.unwrap();
+        assert!(!feed.contains("<entry>") && !feed.contains("<item>"));
+    }
+}
+
+#[tokio::test]
+async fn unchanged_feeds_are_not_sent_again() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    publish_newsletter(&test_app, "First issue", true).await;
+
+    for feed in ["feed.xml", "rss.xml"] {
+        let response = test_app.get_feed(feed, &[]).await;
+        let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+        let last_modified = response.headers()["Last-Modified"]
+            .to_str()
+            .unwrap()
+            .to_owned();
+
+        // Act
+        let by_etag = test_app.get_feed(feed, &[("If-None-Match", &etag)]).await;
+        let by_date = test_app
+            .get_feed(feed, &[("If-Modified-Since", &last_modified)])
+            .await;
+        let stale_etag = test_app
+            .get_feed(
+                feed,
+                &[
+                    ("If-None-Match", "\"stale\""),
+                    ("If-Modified-Since", &last_modified),
+                ],
+            )
+            .await;
+
+        // Assert
+        assert_eq!(by_etag.status().as_u16(), 304);
+        assert_eq!(by_etag.headers()["ETag"], etag.as_str());
+        assert_eq!(by_date.status().as_u16(), 304);
+        assert_eq!(stale_etag.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn unchanged_feeds_are_not_rendered_again() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    publish_newsletter(&test_app, "First issue", true).await;
+    let response = test_app.get_feed("feed.xml", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+    // Sabotage the feed entries: only the revision of the archive is left to read.
+    sqlx::query!("ALTER TABLE newsletter_issues DROP COLUMN html_content;")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = test_app
+        .get_feed("feed.xml", &[("If-None-Match", &etag)])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 304);
+}
+
+#[tokio::test]
+async fn feeds_change_when_an_issue_is_archived() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    publish_newsletter(&test_app, "First issue", true).await;
+    let response = test_app.get_feed("feed.xml", &[]).await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+    // Act
+    publish_newsletter(&test_app, "Second issue", true).await;
+    let response = test_app
+        .get_feed("feed.xml", &[("If-None-Match", &etag)])
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"], etag.as_str());
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains("Second issue"));
+}
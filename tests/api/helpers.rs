@@ -7,18 +7,22 @@ use fake::{
     faker::{internet::en::SafeEmail, name::en::Name},
 };
 use once_cell::sync::Lazy;
-use sqlx::PgPool;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
 use zero2prod::{
-    configuration::{DatabaseSettings, get_configuration},
+    configuration::{
+        DatabaseSettings, EmailLayoutSettings, HtmlSanitizerSettings, Settings, get_configuration,
+    },
+    confirmation_outbox::try_send_confirmation_email,
     domain::SubscriberEmail,
+    email_client::EmailClient,
+    form_token::FormTokens,
     get_subscriber, init_subscriber,
+    issue_delivery_worker::{AttachmentCache, ExecutionOutcome, try_execute_task},
     startup::{Application, get_connection_pool},
 };
 
-const TEST_DATABASE_NAME: &str = "emails_test";
-
 static TRACING: Lazy<()> = Lazy::new(|| {
     if std::env::var("TEST_LOG").is_ok() {
         let subscriber = get_subscriber("test".into(), "debug".into(), std::io::stdout);
@@ -29,6 +33,9 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+/// The bearer token `/metrics` is protected with.
+pub const METRICS_TOKEN: &str = "metrics-token";
+
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
@@ -52,7 +59,7 @@ impl TestUser {
         .await;
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut OsRng);
         let password_hash = Argon2::new(
             Algorithm::Argon2id,
@@ -85,6 +92,10 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub email_layout: EmailLayoutSettings,
+    pub html_sanitizer: HtmlSanitizerSettings,
+    pub form_tokens: FormTokens,
 }
 
 pub struct ConfirmationLinks {
@@ -93,6 +104,42 @@ pub struct ConfirmationLinks {
 }
 
 impl TestApp {
+    pub async fn dispatch_all_pending_emails(&self) {
+        let mut attachment_cache = AttachmentCache::default();
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.address,
+                &self.email_layout,
+                &self.html_sanitizer,
+                &mut attachment_cache,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Send the confirmation emails waiting in the outbox, as the background worker would.
+    pub async fn dispatch_all_confirmation_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_send_confirmation_email(
+                &self.db_pool,
+                &self.email_client,
+                &self.address,
+                &self.email_layout,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn get_login_html(&self) -> String {
         self.api_client
             .get(format!("{}/login", &self.address))
@@ -118,11 +165,42 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    /// A subscription form token old enough to be accepted right away.
+    pub fn form_token(&self) -> String {
+        self.form_tokens
+            .issue_at(chrono::Utc::now() - chrono::Duration::minutes(1))
+    }
+
+    /// Subscribe, then send the confirmation email right away.
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        let response = self.post_subscriptions_without_dispatch(body).await;
+        self.dispatch_all_confirmation_emails().await;
+        response
+    }
+
+    pub async fn post_subscriptions_without_dispatch(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(format!("{}/subscribe", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body)
+            .body(format!("{body}&form_token={}", self.form_token()))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_confirmation(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/confirm", &self.address))
+            .form(&[("subscription_token", subscription_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&serde_json::json!({ "subscription_token": subscription_token }))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -207,6 +285,18 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_preview_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/preview", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/newsletters", &self.address))
@@ -218,17 +308,234 @@ impl TestApp {
     pub async fn get_publish_newsletter_html(&self) -> String {
         self.get_publish_newsletter().await.text().await.unwrap()
     }
+
+    pub async fn get_suppressions(&self, address: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("{}/admin/suppressions", &self.address));
+        if let Some(address) = address {
+            request = request.query(&[("address", address)]);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_suppressions_html(&self, address: Option<&str>) -> String {
+        self.get_suppressions(address).await.text().await.unwrap()
+    }
+
+    pub async fn post_add_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_remove_suppression<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/suppressions/remove", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_ready(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/ready", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/metrics", &self.address))
+            .bearer_auth(METRICS_TOKEN)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics_text(&self) -> String {
+        self.get_metrics().await.text().await.unwrap()
+    }
+
+    pub async fn get_layouts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/layouts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_layout<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/layouts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_layout<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/layouts/delete", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_archive_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/archive", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_archived_issue(&self, slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/archive/{}", &self.address, slug))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Fetch `/feed.xml` or `/rss.xml`, with conditional request headers if any.
+    pub async fn get_feed(&self, feed: &str, headers: &[(&str, &str)]) -> reqwest::Response {
+        let mut request = self.api_client.get(format!("{}/{}", &self.address, feed));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_attachments_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/attachments", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_upload_attachment(
+        &self,
+        form: reqwest::multipart::Form,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/attachments", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_delete_attachment<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/attachments/delete", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Post the issue action `action` (`pause`, `resume` or `cancel`).
+    pub async fn post_issue_action<Body>(&self, action: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/issues/{}", &self.address, action))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log_html(&self, query: &[(&str, &str)]) -> String {
+        self.get_audit_log(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_audit_log_export(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit/export", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application with test-specific settings on top of the test defaults.
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
     let configuration = {
         let mut configuration = get_configuration().expect("Failed to read configuration.");
-        configuration.database.database_name = TEST_DATABASE_NAME.to_owned();
+        // Use a different database for each test case
+        configuration.database.database_name = Uuid::new_v4().to_string();
         configuration.application.port = 0;
         configuration.email_client.base_url = email_server.uri().to_string();
+        configuration.email_layout.physical_address = "1 Test Street, Testville".into();
+        // Small enough to exceed without slowing the tests down
+        configuration.attachments.max_file_bytes = 10_000;
+        configuration.attachments.max_total_bytes = 15_000;
+        // Test cases share Redis, keep their rate limiting buckets apart
+        configuration.rate_limit.key_prefix = format!("rate_limit:{}", Uuid::new_v4());
+        configuration.metrics.bearer_token = Some(METRICS_TOKEN.into());
+        customize(&mut configuration);
 
         configuration
     };
@@ -255,6 +562,16 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
+        email_client: configuration
+            .email_client
+            .client()
+            .expect("Invalid email client configuration."),
+        email_layout: configuration.email_layout.clone(),
+        html_sanitizer: configuration.html_sanitizer.clone(),
+        form_tokens: FormTokens::new(
+            configuration.hmac_secret.clone(),
+            configuration.bot_protection.clone(),
+        ),
     };
     test_app.test_user.store(&test_app.db_pool).await;
 
@@ -262,9 +579,25 @@ pub async fn spawn_app() -> TestApp {
 }
 
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
-    let connection = PgPool::connect_with(config.db_options());
+    // Create database
+    let mut connection = PgConnection::connect_with(&config.db_options().database("postgres"))
+        .await
+        .expect("Failed to connect to Postgres.");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database.");
+
+    // Migrate database
+    let connection_pool = PgPool::connect_with(config.db_options())
+        .await
+        .expect("Failed to connect to Postgres.");
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
 
-    connection.await.expect("Failed to connect to Postgres.")
+    connection_pool
 }
 
 pub trait UrlEncodable {
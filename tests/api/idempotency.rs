@@ -0,0 +1,500 @@
+use std::time::Duration;
+
+use uuid::Uuid;
+use zero2prod::{
+    idempotency::{IdempotencyKey, delete_expired_entries, release, save_response},
+    utils::see_other,
+};
+
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+fn newsletter_request_body(idempotency_key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    })
+}
+
+async fn count_issues(test_app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+fn user_scope(test_app: &TestApp) -> String {
+    format!("user:{}", test_app.test_user.user_id)
+}
+
+/// Move the idempotency entry of `key` back in time.
+async fn age_entry(test_app: &TestApp, key: &str, age_seconds: f64) {
+    sqlx::query!(
+        r#"
+            UPDATE idempotency
+            SET created_at = now() - make_interval(secs => $3)
+            WHERE scope = $1 AND idempotency_key = $2
+        "#,
+        user_scope(test_app),
+        key,
+        age_seconds
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn a_publication_keyed_by_header_is_replayed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    });
+    let publish = || {
+        test_app
+            .api_client
+            .post(format!("{}/admin/newsletters", &test_app.address))
+            .header("Idempotency-Key", &key)
+            .form(&body)
+            .send()
+    };
+
+    // Act
+    let first = publish().await.unwrap();
+    let retry = publish().await.unwrap();
+
+    // Assert
+    assert_is_redirect_to(&first, "/admin/newsletters");
+    assert!(first.headers().get("Idempotent-Replayed").is_none());
+    assert_is_redirect_to(&retry, "/admin/newsletters");
+    assert_eq!(retry.headers().get("Idempotent-Replayed").unwrap(), "true");
+    assert_eq!(count_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn an_expired_response_is_not_replayed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    age_entry(&test_app, &key, 2.0 * 86_400.0).await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&test_app).await, 2);
+}
+
+#[tokio::test]
+async fn a_retry_takes_over_a_stale_in_progress_request() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    // A request that never saved its response, e.g. because the instance crashed.
+    sqlx::query!(
+        r#"
+            INSERT INTO idempotency (scope, idempotency_key, created_at)
+            VALUES ($1, $2, now() - interval '1 hour')
+        "#,
+        user_scope(&test_app),
+        key
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&test_app).await, 1);
+    let status_code = sqlx::query_scalar!(
+        "SELECT response_status_code FROM idempotency WHERE idempotency_key = $1",
+        key
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(status_code, Some(303));
+}
+
+#[tokio::test]
+async fn expired_entries_are_swept() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let (expired, fresh) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+    for key in [&expired, &fresh] {
+        test_app
+            .post_publish_newsletter(&newsletter_request_body(key))
+            .await;
+    }
+    age_entry(&test_app, &expired, 2.0 * 86_400.0).await;
+
+    // Act
+    let n_deleted = delete_expired_entries(&test_app.db_pool, Duration::from_secs(86_400))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(n_deleted, 1);
+    let keys = sqlx::query_scalar!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, [fresh]);
+}
+
+async fn post_subscriptions_with_key(
+    test_app: &TestApp,
+    body: &str,
+    idempotency_key: &str,
+) -> reqwest::Response {
+    test_app
+        .api_client
+        .post(format!("{}/subscribe", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Idempotency-Key", idempotency_key)
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn a_retried_subscription_is_replayed_rather_than_processed_again() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+        test_app.form_token()
+    );
+    let key = Uuid::new_v4().to_string();
+
+    // Act
+    let first = post_subscriptions_with_key(&test_app, &body, &key).await;
+    // Without idempotency, the form token would be rejected as replayed.
+    let retry = post_subscriptions_with_key(&test_app, &body, &key).await;
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first.headers().get("Idempotent-Replayed").is_none());
+    assert_eq!(retry.status().as_u16(), 200);
+    assert_eq!(retry.headers().get("Idempotent-Replayed").unwrap(), "true");
+}
+
+#[tokio::test]
+async fn reusing_a_key_for_a_different_request_is_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let key = Uuid::new_v4().to_string();
+    let body = |email: &str| {
+        format!(
+            "name=le%20guin&email={email}&form_token={}",
+            test_app.form_token()
+        )
+    };
+    let response =
+        post_subscriptions_with_key(&test_app, &body("ursula_le_guin%40gmail.com"), &key).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act
+    let response = post_subscriptions_with_key(&test_app, &body("ursula%40gmail.com"), &key).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn error_responses_are_not_saved() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let key = Uuid::new_v4().to_string();
+    let invalid_body = format!(
+        "name=le%20guin&email=not-an-email&form_token={}",
+        test_app.form_token()
+    );
+    let response = post_subscriptions_with_key(&test_app, &invalid_body, &key).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // Act - Fix the request and retry it with the same key
+    let valid_body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+        test_app.form_token()
+    );
+    let response = post_subscriptions_with_key(&test_app, &valid_body, &key).await;
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn keys_are_scoped_to_the_client_on_anonymous_routes() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let key = Uuid::new_v4().to_string();
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+        test_app.form_token()
+    );
+
+    // Act
+    post_subscriptions_with_key(&test_app, &body, &key).await;
+
+    // Assert
+    let scope = sqlx::query_scalar!(
+        "SELECT scope FROM idempotency WHERE idempotency_key = $1",
+        key
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(scope, "client:127.0.0.1");
+}
+
+#[tokio::test]
+async fn forwarded_for_headers_do_not_change_the_scope_of_anonymous_keys() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let key = Uuid::new_v4().to_string();
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+        test_app.form_token()
+    );
+
+    // Act
+    let response = test_app
+        .api_client
+        .post(format!("{}/subscribe", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Idempotency-Key", &key)
+        .header("X-Forwarded-For", "203.0.113.7")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let scope = sqlx::query_scalar!(
+        "SELECT scope FROM idempotency WHERE idempotency_key = $1",
+        key
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(scope, "client:127.0.0.1");
+}
+
+#[tokio::test]
+async fn a_publication_is_rolled_back_if_its_response_cannot_be_saved() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    // Sabotage the idempotency table
+    sqlx::query!(
+        "ALTER TABLE idempotency ADD CONSTRAINT no_responses CHECK (response_status_code IS NULL);"
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    let key = Uuid::new_v4().to_string();
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    assert_eq!(count_issues(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn a_replayed_publication_is_confirmed_again() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+    test_app.get_publish_newsletter_html().await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&test_app).await, 1);
+    let html_page = test_app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("The newsletter issue has been accepted"));
+}
+
+#[tokio::test]
+async fn a_rejected_publication_is_not_replayed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    let invalid_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": key
+    });
+    test_app.post_publish_newsletter(&invalid_body).await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(count_issues(&test_app).await, 1);
+}
+
+/// Claim `key` as if a slow request were processing it.
+async fn start_processing(test_app: &TestApp, key: &str) {
+    sqlx::query!(
+        "INSERT INTO idempotency (scope, idempotency_key, created_at) VALUES ($1, $2, now())",
+        user_scope(test_app),
+        key
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn a_retry_waits_for_the_response_of_the_request_in_progress() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    start_processing(&test_app, &key).await;
+    let idempotency_key = IdempotencyKey::try_from(key.clone()).unwrap();
+    let complete_processing = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let response = see_other("/admin/newsletters");
+        save_response(
+            &test_app.db_pool,
+            &idempotency_key,
+            &user_scope(&test_app),
+            response,
+        )
+        .await
+        .unwrap();
+    };
+
+    // Act
+    let body = newsletter_request_body(&key);
+    let (response, _) = tokio::join!(test_app.post_publish_newsletter(&body), complete_processing);
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_eq!(
+        response.headers().get("Idempotent-Replayed").unwrap(),
+        "true"
+    );
+    assert_eq!(count_issues(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn a_retry_takes_over_when_the_request_in_progress_fails() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    start_processing(&test_app, &key).await;
+    let idempotency_key = IdempotencyKey::try_from(key.clone()).unwrap();
+    let fail_processing = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        release(&test_app.db_pool, &idempotency_key, &user_scope(&test_app))
+            .await
+            .unwrap();
+    };
+
+    // Act
+    let body = newsletter_request_body(&key);
+    let (response, _) = tokio::join!(test_app.post_publish_newsletter(&body), fail_processing);
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(response.headers().get("Idempotent-Replayed").is_none());
+    assert_eq!(count_issues(&test_app).await, 1);
+}
+
+#[tokio::test]
+async fn a_retry_gives_up_with_a_conflict_if_the_request_in_progress_does_not_complete() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let key = Uuid::new_v4().to_string();
+    start_processing(&test_app, &key).await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body(&key))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers().get("Retry-After").unwrap(), "1");
+    assert_eq!(count_issues(&test_app).await, 0);
+}
@@ -0,0 +1,210 @@
+use wiremock::{
+    Mock, MockBuilder, ResponseTemplate,
+    matchers::{method, path},
+};
+use zero2prod::delivery_log::get_issue_deliveries;
+
+use crate::helpers::{
+    TestApp, UrlEncodable, assert_is_redirect_to, fake_email, fake_name, spawn_app,
+};
+
+async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        fake_email().as_ref().url_encode()
+    );
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_newsletter(test_app: &TestApp) -> uuid::Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query_scalar!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn issue_status(test_app: &TestApp, issue_id: uuid::Uuid) -> String {
+    sqlx::query_scalar!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn n_sent_emails(test_app: &TestApp) -> usize {
+    test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .len()
+}
+
+fn when_sending_an_email() -> MockBuilder {
+    Mock::given(path("/email")).and(method("POST"))
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_issue_action(
+            "cancel",
+            &serde_json::json!({ "newsletter_issue_id": uuid::Uuid::new_v4().to_string() }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn paused_issues_are_delivered_once_resumed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&test_app).await;
+    let sent_before = n_sent_emails(&test_app).await;
+    let form = serde_json::json!({ "newsletter_issue_id": issue_id.to_string() });
+
+    // Act - Part 1 - Pause
+    let response = test_app.post_issue_action("pause", &form).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1 - Nothing was sent
+    let html_page = test_app.get_issues_html().await;
+    assert!(html_page.contains("<p><i>The issue has been paused.</i></p>"));
+    assert!(html_page.contains(r#"action="/admin/issues/resume""#));
+    assert_eq!(issue_status(&test_app, issue_id).await, "paused");
+    assert_eq!(n_sent_emails(&test_app).await, sent_before);
+
+    // Act - Part 2 - Resume
+    let response = test_app.post_issue_action("resume", &form).await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2 - Delivered
+    assert_eq!(n_sent_emails(&test_app).await, sent_before + 2);
+    assert_eq!(issue_status(&test_app, issue_id).await, "sent");
+}
+
+#[tokio::test]
+async fn cancelled_issues_drop_their_queued_emails() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let issue_id = publish_newsletter(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_issue_action(
+            "cancel",
+            &serde_json::json!({ "newsletter_issue_id": issue_id.to_string() }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = test_app.get_issues_html().await;
+    assert!(html_page.contains("The issue has been cancelled"));
+    assert_eq!(issue_status(&test_app, issue_id).await, "cancelled");
+    let n_queued = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_queued, 0);
+    let deliveries = get_issue_deliveries(&test_app.db_pool, issue_id)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|d| d.outcome == "cancelled"));
+    let action = sqlx::query_scalar!(
+        "SELECT action FROM audit_log WHERE target = $1",
+        issue_id.to_string()
+    )
+    .fetch_all(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert!(action.contains(&"newsletter_cancelled".to_owned()));
+}
+
+#[tokio::test]
+async fn issues_that_are_not_being_sent_cannot_be_paused() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    // Without recipients, the issue is sent right away.
+    let issue_id = publish_newsletter(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_issue_action(
+            "pause",
+            &serde_json::json!({ "newsletter_issue_id": issue_id.to_string() }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = test_app.get_issues_html().await;
+    assert!(html_page.contains("Only issues being sent can be paused."));
+    assert_eq!(issue_status(&test_app, issue_id).await, "sent");
+}
@@ -0,0 +1,334 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, UrlEncodable, assert_is_redirect_to, fake_name, spawn_app};
+
+async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        "ursula@example.com".url_encode()
+    );
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn create_layout(test_app: &TestApp, name: &str) -> uuid::Uuid {
+    let response = test_app
+        .post_create_layout(&serde_json::json!({
+            "name": name,
+            "html_template": "<div class=\"brand\">{{ content }}</div><p>{{ physical_address }}</p>\
+                {% if unsubscribe_url %}<a href=\"{{ unsubscribe_url }}\">Unsubscribe</a>{% endif %}",
+            "text_template": "ACME NEWS\n\n{{ content }}\n\n{{ physical_address }}\
+                {% if unsubscribe_url %}\nUnsubscribe: {{ unsubscribe_url }}{% endif %}",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/layouts");
+    sqlx::query!("SELECT layout_id FROM email_layouts WHERE name = $1", name)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .layout_id
+}
+
+async fn publish_and_deliver(test_app: &TestApp, layout_id: &str) -> serde_json::Value {
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "layout_id": layout_id,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&email_request.body).unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_layouts() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_create_layout(&serde_json::json!({
+            "name": "Branded",
+            "html_template": "{{ content }}",
+            "text_template": "{{ content }}",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn created_layouts_are_listed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    create_layout(&test_app, "Branded").await;
+
+    // Assert
+    let html_page = test_app.get_layouts_html().await;
+    assert!(html_page.contains("The Branded layout has been added."));
+    assert!(html_page.contains("ACME NEWS"));
+}
+
+#[tokio::test]
+async fn layouts_without_the_content_placeholder_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_create_layout(&serde_json::json!({
+            "name": "Footer only",
+            "html_template": "<p>Footer</p>",
+            "text_template": "{{ content }}",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let html_page = test_app.get_layouts_html().await;
+    assert!(html_page.contains("The HTML template is not valid"));
+    let n_layouts = sqlx::query!("SELECT count(*) as \"count!\" FROM email_layouts")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_layouts, 0);
+}
+
+#[tokio::test]
+async fn newsletters_are_wrapped_in_the_built_in_layout_by_default() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let body = publish_and_deliver(&test_app, "").await;
+
+    // Assert
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.contains("<p>Newsletter body as HTML</p>"));
+    assert!(html_body.contains("/subscriptions/unsubscribe?subscription_token="));
+    assert!(html_body.contains("1 Test Street, Testville"));
+    assert!(text_body.starts_with("Newsletter body as plain text"));
+    assert!(text_body.contains("Unsubscribe: "));
+    assert!(text_body.ends_with("1 Test Street, Testville"));
+}
+
+#[tokio::test]
+async fn an_issue_can_pick_its_own_layout() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let layout_id = create_layout(&test_app, "Branded").await;
+
+    // Act
+    let body = publish_and_deliver(&test_app, &layout_id.to_string()).await;
+
+    // Assert
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.starts_with(
+        "<div class=\"brand\"><p>Newsletter body as HTML</p></div><p>1 Test Street, Testville</p>\
+        <a href=\"http://127.0.0.1"
+    ));
+    assert!(text_body.starts_with(
+        "ACME NEWS\n\nNewsletter body as plain text\n\n1 Test Street, Testville\nUnsubscribe: "
+    ));
+}
+
+#[tokio::test]
+async fn layouts_used_by_issues_being_sent_cannot_be_deleted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let layout_id = create_layout(&test_app, "Branded").await;
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "layout_id": layout_id.to_string(),
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_delete_layout(&serde_json::json!({ "layout_id": layout_id }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/layouts");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = test_app.get_layouts_html().await;
+    assert!(html_page.contains("The layout is used by issues that are being sent"));
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().contains("ACME NEWS"));
+}
+
+#[tokio::test]
+async fn layouts_used_by_archived_issues_cannot_be_deleted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let layout_id = create_layout(&test_app, "Branded").await;
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "layout_id": layout_id.to_string(),
+            "publish_to_archive": "on",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Act
+    let response = test_app
+        .post_delete_layout(&serde_json::json!({ "layout_id": layout_id }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let html_page = test_app.get_layouts_html().await;
+    assert!(html_page.contains("The layout is used by issues that are being sent"));
+    assert!(html_page.contains("ACME NEWS"));
+}
+
+#[tokio::test]
+async fn layouts_can_be_deleted_once_their_issues_are_sent() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let layout_id = create_layout(&test_app, "Branded").await;
+    publish_and_deliver(&test_app, &layout_id.to_string()).await;
+
+    // Act
+    let response = test_app
+        .post_delete_layout(&serde_json::json!({ "layout_id": layout_id }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let html_page = test_app.get_layouts_html().await;
+    assert!(html_page.contains("The layout has been deleted."));
+    assert!(!html_page.contains("ACME NEWS"));
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_wrapped_in_the_layout() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("1 Test Street, Testville"));
+    assert!(!html_body.contains("Unsubscribe"));
+    assert!(
+        body["TextBody"]
+            .as_str()
+            .unwrap()
+            .ends_with("1 Test Street, Testville")
+    );
+}
+
+#[tokio::test]
+async fn layout_names_are_escaped() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    create_layout(&test_app, "<script>alert('boom')</script>").await;
+
+    // Assert
+    let html_page = test_app.get_layouts_html().await;
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;alert('boom')&lt;/script&gt;"));
+}
@@ -1,8 +1,24 @@
 mod admin_dashboard;
+mod archive;
+mod attachments;
+mod audit;
+mod bot_protection;
 mod change_password;
+mod deliveries;
+mod feeds;
 mod health_check;
 mod helpers;
+mod idempotency;
+mod issues;
+mod layouts;
 mod login;
+mod metrics;
 mod newsletter;
+mod rate_limit;
+mod ready;
+mod request_id;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod suppressions;
+mod trace_propagation;
@@ -0,0 +1,201 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+/// The value of the first sample whose line starts with `prefix`.
+/// Tests run concurrently in the same process, so counters are compared, not matched exactly.
+fn sample(metrics: &str, prefix: &str) -> f64 {
+    metrics
+        .lines()
+        .find(|line| line.starts_with(prefix))
+        .and_then(|line| line.rsplit(' ').next())
+        .map(|value| value.parse().unwrap())
+        .unwrap_or(0.0)
+}
+
+#[tokio::test]
+async fn metrics_are_exposed_in_the_prometheus_format() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.get_metrics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    let metrics = response.text().await.unwrap();
+    assert!(metrics.contains("# TYPE issue_delivery_queue_depth gauge"));
+    assert!(metrics.contains("# TYPE subscriptions_created_total counter"));
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route_and_status() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let series = r#"http_requests_total{method="GET",route="/health_check",status="200"}"#;
+    let before = sample(&test_app.get_metrics_text().await, series);
+
+    // Act
+    test_app
+        .api_client
+        .get(format!("{}/health_check", &test_app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let after = sample(&test_app.get_metrics_text().await, series);
+    assert!(after > before);
+}
+
+#[tokio::test]
+async fn failed_logins_are_counted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let series = r#"login_attempts_total{outcome="failure"}"#;
+    let before = sample(&test_app.get_metrics_text().await, series);
+
+    // Act
+    let response = test_app
+        .post_login(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Assert
+    let after = sample(&test_app.get_metrics_text().await, series);
+    assert!(after > before);
+}
+
+#[tokio::test]
+async fn subscriptions_and_confirmations_are_counted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let metrics = test_app.get_metrics_text().await;
+    let created_before = sample(&metrics, "subscriptions_created_total ");
+    let confirmed_before = sample(&metrics, "subscriptions_confirmed_total ");
+
+    // Act
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let metrics = test_app.get_metrics_text().await;
+    assert!(sample(&metrics, "subscriptions_created_total ") > created_before);
+    assert!(sample(&metrics, "subscriptions_confirmed_total ") > confirmed_before);
+    assert!(metrics.contains(r#"email_client_request_duration_seconds_count{outcome="success"}"#));
+}
+
+#[tokio::test]
+async fn queue_depth_and_emails_sent_are_reported() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap();
+    test_app.test_user.login(&test_app).await;
+
+    // Act - Part 1 - Publish
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let metrics = test_app.get_metrics_text().await;
+    assert_eq!(sample(&metrics, "issue_delivery_queue_depth "), 1.0);
+
+    let emails_sent_before = sample(&metrics, "emails_sent_total ");
+
+    // Act - Part 2 - Deliver
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let metrics = test_app.get_metrics_text().await;
+    assert_eq!(sample(&metrics, "issue_delivery_queue_depth "), 0.0);
+    assert!(sample(&metrics, "emails_sent_total ") >= emails_sent_before + 1.0);
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let sent = format!(r#"issue_emails_sent{{issue="{issue_id}"}} "#);
+    let failed = format!(r#"issue_emails_failed{{issue="{issue_id}"}} "#);
+    assert_eq!(sample(&metrics, &sent), 1.0);
+    assert!(metrics.contains(&failed));
+    assert_eq!(sample(&metrics, &failed), 0.0);
+}
+
+#[tokio::test]
+async fn metrics_require_the_bearer_token() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let without_token = reqwest::get(format!("{}/metrics", test_app.address))
+        .await
+        .unwrap();
+    let with_a_wrong_token = reqwest::Client::new()
+        .get(format!("{}/metrics", test_app.address))
+        .bearer_auth("wrong-token")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    for response in [without_token, with_a_wrong_token] {
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    }
+}
+
+#[tokio::test]
+async fn metrics_are_not_served_without_a_token_configured() {
+    // Arrange
+    let test_app = spawn_app_with(|settings| settings.metrics.bearer_token = None).await;
+
+    // Act
+    let response = test_app.get_metrics().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -7,16 +7,14 @@ use wiremock::{
 };
 
 use crate::helpers::{
-    ConfirmationLinks, TestApp, assert_is_redirect_to, fake_email, fake_name, spawn_app,
+    ConfirmationLinks, TestApp, UrlEncodable, assert_is_redirect_to, fake_email, fake_name,
+    spawn_app,
 };
 
 async fn create_unconfirmed_subscriber(test_app: &TestApp) -> ConfirmationLinks {
     let name = fake_name();
     let email = fake_email().as_ref().to_owned();
-    let body = urlencoding::encode(&serde_json::json!({
-        "name": name,
-        "email": email
-        }).to_string()).to_string();
+    let body = format!("name={}&email={}", name.url_encode(), email.url_encode());
 
     let _mock_guard = Mock::given(path("/email"))
         .and(method("POST"))
@@ -115,36 +113,51 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
 }
 
 #[tokio::test]
-async fn newsletters_returns_400_for_invalid_data() {
+async fn invalid_newsletters_are_sent_back_with_an_error_message() {
     // Arrange
     let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
     let test_cases = vec![
         (
             serde_json::json!({
-                "content": {
-                    "text": "Newsletter body as plain text",
-                    "html": "<p>Newsletter body as HTML</p>",
-                }
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
             }),
-            "missing title",
+            "The title cannot be empty.",
         ),
         (
             serde_json::json!({"title": "Newsletter!"}),
-            "missing content",
+            "The newsletter must have either Markdown content",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter!",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<div><b>Newsletter body as HTML</div>",
+            }),
+            "The HTML content is not well-formed",
         ),
     ];
 
-    for (invalid_body, error_message) in test_cases {
+    for (mut invalid_body, error_message) in test_cases {
+        invalid_body["idempotency_key"] = uuid::Uuid::new_v4().to_string().into();
+
         // Act
         let response = test_app.post_publish_newsletter(&invalid_body).await;
 
         // Assert
-        assert_eq!(
-            response.status().as_u16(),
-            400,
-            "The API did not fail with 400 Bad Request when the payload was {error_message}."
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = test_app.get_publish_newsletter_html().await;
+        assert!(
+            html_page.contains(error_message),
+            "The publish form did not show `{error_message}`."
         );
     }
+    let n_issues = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_issues, 0);
 }
 
 #[tokio::test]
@@ -311,6 +324,8 @@ async fn concurrent_form_submission_is_handled_gracefully() {
         response1.text().await.unwrap(),
         response2.text().await.unwrap()
     );
+    // Only one issue was published, hence a single email.
+    test_app.dispatch_all_pending_emails().await;
 }
 
 fn when_sending_an_email() -> MockBuilder {
@@ -360,6 +375,318 @@ async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
         .mount(&test_app.email_server)
         .await;
 
-    let response = test_app.post_publish_newsletter(&newsletter_request_body).await;
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body)
+        .await;
     assert_eq!(response.status().as_u16(), 303);
 }
+
+#[tokio::test]
+async fn merge_fields_are_rendered_for_each_recipient() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let name = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .name;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name }}! Unsubscribe: {{ unsubscribe_url }}",
+        "html_content": "<p>Hi {{ name }}!</p><a href=\"{{ preferences_url }}\">Preferences</a>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.last().unwrap().body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(text_body.starts_with(&format!("Hi {name}! Unsubscribe: ")));
+    assert!(text_body.contains("/subscriptions/unsubscribe?subscription_token="));
+    assert!(html_body.contains("/subscriptions/preferences?subscription_token="));
+}
+
+#[tokio::test]
+async fn newsletters_with_an_invalid_template_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    let test_cases = vec![
+        (
+            "Hi {{ name",
+            "<p>Hi</p>",
+            "The text content is not a valid template",
+        ),
+        (
+            "Hi",
+            "<p>Hi {{ nmae }}</p>",
+            "The HTML content is not a valid template",
+        ),
+    ];
+
+    for (text_content, html_content, error_message) in test_cases {
+        // Act
+        let response = test_app
+            .post_publish_newsletter(&serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": text_content,
+                "html_content": html_content,
+                "idempotency_key": uuid::Uuid::new_v4().to_string()
+            }))
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        let html_page = test_app.get_publish_newsletter_html().await;
+        assert!(html_page.contains(error_message));
+    }
+    let n_issues = sqlx::query!("SELECT count(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn markdown_newsletters_are_delivered_as_html_and_plain_text() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let markdown = "# Big news\n\nRead [the post](https://example.com).";
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": markdown,
+            "text_content": "",
+            "html_content": "",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.last().unwrap().body).unwrap();
+    assert!(
+        body["HtmlBody"]
+            .as_str()
+            .unwrap()
+            .contains("<h1>Big news</h1>")
+    );
+    assert!(
+        body["TextBody"]
+            .as_str()
+            .unwrap()
+            .starts_with("Big news\n\nRead the post (https://example.com).")
+    );
+    let saved = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.markdown_content.as_deref(), Some(markdown));
+}
+
+#[tokio::test]
+async fn the_preview_shows_both_renderings_without_publishing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "# Hi {{ name }}",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("&lt;h1&gt;Hi Ursula Le Guin&lt;/h1&gt;"));
+    assert!(html_page.contains("<pre>Hi Ursula Le Guin"));
+    // The preview is wrapped in the layout the issue would be sent with.
+    assert!(html_page.contains("Unsubscribe: https://example.com/subscriptions/unsubscribe"));
+    let n_issues = sqlx::query!("SELECT count(*) as \"count!\" FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn scripts_are_removed_from_delivered_newsletters() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p onmouseover=\"steal()\">Hi</p>\
+                <script>alert('boom')</script>\
+                {% autoescape false %}{{ '<script>alert(1)</script>' }}{% endautoescape %}",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.last().unwrap().body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<p>Hi</p>"));
+    assert!(!html_body.contains("<script"));
+    assert!(!html_body.contains("onmouseover"));
+}
+
+#[tokio::test]
+async fn the_preview_escapes_the_title_and_sanitizes_the_content() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_preview_newsletter(&serde_json::json!({
+            "title": "<script>alert('title')</script>",
+            "text_content": "<script>alert('text')</script>",
+            "html_content": "<p>Hi</p><script>alert('html')</script>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;alert('title')&lt;/script&gt;"));
+    let srcdoc = html_page
+        .split(r#"srcdoc=""#)
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .unwrap();
+    assert!(srcdoc.contains("&lt;p&gt;Hi&lt;/p&gt;"));
+    assert!(!srcdoc.contains("alert('html')"));
+}
+
+#[tokio::test]
+async fn published_issues_record_their_author_recipients_and_completion() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    create_confirmed_subscriber(&test_app).await;
+    create_unconfirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert - Part 1 - Still sending
+    let issue = sqlx::query!(
+        "SELECT created_by, status, recipient_count, published_at, completed_at \
+        FROM newsletter_issues"
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.created_by, Some(test_app.test_user.user_id));
+    assert_eq!(issue.status, "sending");
+    assert_eq!(issue.recipient_count, Some(2));
+    assert!(issue.published_at.is_some());
+    assert!(issue.completed_at.is_none());
+
+    // Act - Part 2 - Deliver
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2 - Sent
+    let issue = sqlx::query!("SELECT status, published_at, completed_at FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+    assert!(issue.completed_at.unwrap() >= issue.published_at.unwrap());
+}
+
+#[tokio::test]
+async fn issues_without_recipients_are_sent_right_away() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let issue = sqlx::query!("SELECT status, recipient_count, completed_at FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+    assert_eq!(issue.recipient_count, Some(0));
+    assert!(issue.completed_at.is_some());
+}
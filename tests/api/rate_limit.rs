@@ -0,0 +1,177 @@
+use wiremock::{Mock, ResponseTemplate, matchers::any};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn subscribing_too_often_from_the_same_ip_is_rejected_with_a_429() {
+    // Arrange
+    let test_app = spawn_app().await;
+    // Invalid requests use up the bucket as well
+    for _ in 0..10 {
+        let response = test_app
+            .post_subscriptions("name=le%20guin&email=not-an-email".into())
+            .await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=not-an-email".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=6).contains(&retry_after));
+}
+
+#[tokio::test]
+async fn subscribing_the_same_address_too_often_is_rejected_with_a_429() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    for _ in 0..3 {
+        test_app
+            .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+            .await;
+    }
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40gmail.com".into())
+        .await;
+    let other_address = test_app
+        .post_subscriptions("name=le%20guin&email=someone_else%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+    assert_eq!(other_address.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirming_too_often_from_the_same_ip_is_rejected_with_a_429() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let url = format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        test_app.address
+    );
+    for _ in 0..10 {
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Act
+    let response = reqwest::get(&url).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn resending_confirmations_to_the_same_address_too_often_is_rejected_with_a_429() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    // One expired link per request, as each link can only be traded once
+    let tokens: Vec<String> = (0..4).map(|i| format!("expiredtoken{i}")).collect();
+    for token in &tokens {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at)
+            VALUES ($1, $2, now() - interval '73 hours')
+            "#,
+            token,
+            subscriber_id,
+        )
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    let mut statuses = Vec::new();
+    for token in &tokens {
+        let response = test_app.post_resend_confirmation(token).await;
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses, [200, 200, 200, 429]);
+}
+
+#[tokio::test]
+async fn logging_in_too_often_from_the_same_ip_is_rejected_with_a_429() {
+    // Arrange
+    // Failed logins are slow to verify, keep the bucket from refilling meanwhile
+    let test_app = spawn_app_with(|settings| {
+        settings.rate_limit.per_ip.refill_interval_milliseconds = 600_000
+    })
+    .await;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    for _ in 0..10 {
+        let response = test_app.post_login(&login_body).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Act
+    let response = test_app.post_login(&login_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+    let n_entries = sqlx::query!(r#"SELECT count(*) AS "count!" FROM audit_log"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_entries, 10);
+}
+
+#[tokio::test]
+async fn a_forwarded_for_header_does_not_reset_the_ip_bucket_by_default() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let mut statuses = Vec::new();
+    for i in 0..11 {
+        let response = test_app
+            .api_client
+            .post(format!("{}/subscribe", &test_app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", format!("10.0.0.{i}"))
+            .body("name=le%20guin&email=not-an-email")
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status().as_u16());
+    }
+
+    // Assert
+    assert_eq!(statuses.last(), Some(&429));
+}
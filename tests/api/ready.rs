@@ -0,0 +1,88 @@
+use uuid::Uuid;
+use zero2prod::issue_delivery_worker::record_heartbeat;
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn ready_returns_200_when_all_dependencies_are_up() {
+    // Arrange
+    let test_app = spawn_app().await;
+    record_heartbeat(&test_app.db_pool, Uuid::new_v4())
+        .await
+        .unwrap();
+
+    // Act
+    let response = test_app.get_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    for dependency in ["postgres", "redis", "migrations", "worker"] {
+        assert_eq!(body["checks"][dependency]["status"], "up", "{dependency}");
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+}
+
+#[tokio::test]
+async fn ready_returns_503_without_a_worker_heartbeat() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.get_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["checks"]["worker"]["status"], "down");
+    assert_eq!(body["checks"]["postgres"]["status"], "up");
+}
+
+#[tokio::test]
+async fn ready_returns_503_when_the_worker_heartbeat_is_stale() {
+    // Arrange
+    let test_app = spawn_app().await;
+    sqlx::query!(
+        "INSERT INTO worker_heartbeats (worker_id, last_seen_at) \
+        VALUES ($1, now() - interval '10 minutes')",
+        Uuid::new_v4()
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = test_app.get_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["worker"]["status"], "down");
+}
+
+#[tokio::test]
+async fn ready_returns_503_when_a_migration_is_missing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    record_heartbeat(&test_app.db_pool, Uuid::new_v4())
+        .await
+        .unwrap();
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations \
+        WHERE version = (SELECT max(version) FROM _sqlx_migrations)",
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = test_app.get_ready().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["migrations"]["status"], "down");
+    assert_eq!(body["checks"]["worker"]["status"], "up");
+}
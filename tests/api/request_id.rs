@@ -0,0 +1,154 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn a_well_formed_request_id_is_echoed_back() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .api_client
+        .get(format!("{}/health_check", &test_app.address))
+        .header("X-Request-Id", "req-42")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.headers()["X-Request-Id"], "req-42");
+}
+
+#[tokio::test]
+async fn a_request_id_is_generated_when_missing_or_malformed() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    for request_id in [None, Some("not a valid id")] {
+        // Act
+        let mut request = test_app
+            .api_client
+            .get(format!("{}/health_check", &test_app.address));
+        if let Some(request_id) = request_id {
+            request = request.header("X-Request-Id", request_id);
+        }
+        let response = request.send().await.unwrap();
+
+        // Assert
+        let generated = response.headers()["X-Request-Id"].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(generated).is_ok());
+    }
+}
+
+#[tokio::test]
+async fn errors_are_rendered_as_an_html_page_by_default() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .api_client
+        .post(format!("{}/subscribe", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "req-42")
+        .body(format!(
+            "name=%3Cb%3Ele%20guin%3C%2Fb%3E&email=not-an-email&form_token={}",
+            test_app.form_token()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>400 Bad Request</h1>"));
+    assert!(html.contains("Request ID: <code>req-42</code>"));
+    assert!(!html.contains("<b>"));
+}
+
+#[tokio::test]
+async fn errors_are_rendered_as_problem_details_when_json_is_accepted() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .api_client
+        .get(format!("{}/subscriptions/confirm", &test_app.address))
+        .header("Accept", "application/json")
+        .header("X-Request-Id", "req-42")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["request_id"], "req-42");
+    assert!(problem["detail"].is_string());
+}
+
+#[tokio::test]
+async fn server_errors_do_not_expose_their_details() {
+    // Arrange
+    let test_app = spawn_app().await;
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email;")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = test_app
+        .api_client
+        .post(format!("{}/subscribe", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/problem+json")
+        .body(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+            test_app.form_token()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let request_id = response.headers()["X-Request-Id"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["request_id"], request_id.as_str());
+    assert!(problem.get("detail").is_none());
+}
+
+#[tokio::test]
+async fn error_responses_without_a_body_are_rendered_too() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .api_client
+        .get(format!("{}/archive/no-such-issue", &test_app.address))
+        .header("X-Request-Id", "req-42")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h1>404 Not Found</h1>"));
+    assert!(html.contains("Request ID: <code>req-42</code>"));
+}
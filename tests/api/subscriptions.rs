@@ -3,7 +3,17 @@ use wiremock::{
     matchers::{method, path},
 };
 
-use crate::helpers::{UrlEncodable, fake_email, fake_name, spawn_app};
+use crate::helpers::{TestApp, UrlEncodable, fake_email, fake_name, spawn_app};
+
+async fn outbox_attempts(test_app: &TestApp) -> Vec<i32> {
+    sqlx::query!("SELECT n_attempts FROM confirmation_email_outbox")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.n_attempts)
+        .collect()
+}
 
 #[tokio::test(flavor = "multi_thread")]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -161,6 +171,74 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn subscribe_does_not_wait_for_the_confirmation_email() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        fake_email().as_ref().url_encode()
+    );
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_subscriptions_without_dispatch(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(outbox_attempts(&test_app).await, vec![0]);
+}
+
+#[tokio::test]
+async fn failed_confirmation_emails_are_retried_later() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        fake_email().as_ref().url_encode()
+    );
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - The provider fails
+    let response = test_app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(outbox_attempts(&test_app).await, vec![1]);
+    // Not due yet
+    test_app.dispatch_all_confirmation_emails().await;
+    assert_eq!(outbox_attempts(&test_app).await, vec![1]);
+
+    // Act - Part 2 - The retry is due
+    sqlx::query!("UPDATE confirmation_email_outbox SET next_attempt_at = now()")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Assert
+    assert!(outbox_attempts(&test_app).await.is_empty());
+}
+
 // This ignore don't remove, it's a test for the database error
 #[tokio::test]
 #[ignore]
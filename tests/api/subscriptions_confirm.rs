@@ -1,9 +1,9 @@
 use wiremock::{
     Mock, ResponseTemplate,
-    matchers::{method, path},
+    matchers::{any, method, path},
 };
 
-use crate::helpers::{fake_email, fake_name, spawn_app};
+use crate::helpers::{TestApp, UrlEncodable, fake_email, fake_name, spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -91,3 +91,302 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, name);
     assert_eq!(saved.status, "confirmed");
 }
+
+/// Subscribe, returning the confirmation link.
+async fn subscribe(test_app: &TestApp, email: &str) -> reqwest::Url {
+    let body = format!("name={}&email={}", "le%20guin", email.url_encode());
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    test_app.get_confirmation_links(email_request).html
+}
+
+#[tokio::test]
+async fn confirming_shows_a_success_page() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Subscription confirmed"));
+}
+
+#[tokio::test]
+async fn confirming_twice_shows_that_the_subscription_is_already_confirmed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    reqwest::get(link.clone()).await.unwrap();
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Your subscription is already confirmed"));
+}
+
+#[tokio::test]
+async fn unknown_tokens_show_an_invalid_link_page() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link is not valid."));
+}
+
+#[tokio::test]
+async fn expired_links_do_not_confirm_and_a_new_one_can_be_requested() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '73 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - Follow the expired link
+    let response = reqwest::get(link.clone()).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has expired."));
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "pending_confirmation");
+
+    // Act - Part 2 - Ask for a new link
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let response = test_app.post_resend_confirmation(&token_of(&link)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("it is valid for 3 days")
+    );
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Act - Part 3 - Follow the new link
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let new_link = test_app.get_confirmation_links(email_request).html;
+    assert_ne!(new_link, link);
+    let response = reqwest::get(new_link).await.unwrap();
+
+    // Assert
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("Subscription confirmed")
+    );
+}
+
+fn token_of(link: &reqwest::Url) -> String {
+    link.query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn links_that_have_not_expired_cannot_be_traded_for_a_new_one() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_resend_confirmation(&token_of(&link)).await;
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has not expired"));
+    let n_tokens = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn an_expired_link_can_only_be_traded_for_a_new_one_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '73 hours'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let token = token_of(&link);
+
+    // Act
+    let first = test_app.post_resend_confirmation(&token).await;
+    let second = test_app.post_resend_confirmation(&token).await;
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 401);
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_welcome_email_is_sent_after_confirmation_if_enabled() {
+    // Arrange
+    let test_app = spawn_app_with(|c| c.confirmation.welcome_email.enabled = true).await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome aboard!");
+    assert_eq!(body["To"], "ursula@example.com");
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Hi le guin, thanks for confirming your subscription!"));
+    assert!(text_body.contains("/subscriptions/unsubscribe?subscription_token="));
+}
+
+#[tokio::test]
+async fn no_welcome_email_is_sent_by_default() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_subscription_is_confirmed_even_if_the_welcome_email_fails() {
+    // Arrange
+    let test_app = spawn_app_with(|c| c.confirmation.welcome_email.enabled = true).await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn concurrent_confirmations_send_a_single_welcome_email() {
+    // Arrange
+    let test_app = spawn_app_with(|c| c.confirmation.welcome_email.enabled = true).await;
+    let link = subscribe(&test_app, "ursula@example.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let (response1, response2) = tokio::join!(reqwest::get(link.clone()), reqwest::get(link));
+
+    // Assert
+    assert_eq!(response1.unwrap().status().as_u16(), 200);
+    assert_eq!(response2.unwrap().status().as_u16(), 200);
+}
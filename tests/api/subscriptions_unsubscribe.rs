@@ -0,0 +1,138 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{method, path},
+};
+
+use crate::helpers::{TestApp, UrlEncodable, fake_email, fake_name, spawn_app};
+
+/// Subscribe and confirm a new subscriber, returning the link to `path` carrying its token.
+async fn confirmed_subscriber_link(test_app: &TestApp, email: &str, path_: &str) -> reqwest::Url {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        email.url_encode()
+    );
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    test_app.post_subscriptions(body).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let mut link = test_app.get_confirmation_links(email_request).html;
+    reqwest::get(link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    link.set_path(path_);
+    link
+}
+
+#[tokio::test]
+async fn unsubscribing_without_a_valid_token_is_rejected_with_a_401() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?subscription_token=unknown",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_only_asks_for_confirmation() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let email = fake_email().as_ref().to_owned();
+    let link = confirmed_subscriber_link(&test_app, &email, "/subscriptions/unsubscribe").await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+    assert!(html_page.contains(r#"name="subscription_token""#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn submitting_the_unsubscribe_form_unsubscribes_and_suppresses_the_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let email = fake_email().as_ref().to_owned();
+    let link = confirmed_subscriber_link(&test_app, &email, "/subscriptions/unsubscribe").await;
+    let (_, subscription_token) = link
+        .query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .unwrap();
+
+    // Act
+    let response = test_app.post_unsubscribe(&subscription_token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .text()
+            .await
+            .unwrap()
+            .contains("You have been unsubscribed")
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    let suppression = sqlx::query!(
+        "SELECT reason FROM suppressions WHERE value = $1",
+        email.to_lowercase()
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(suppression.reason, "unsubscribe");
+}
+
+#[tokio::test]
+async fn submitting_the_unsubscribe_form_without_a_valid_token_is_rejected_with_a_401() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.post_unsubscribe("unknown").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_preferences_page_shows_the_subscription_details() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let email = fake_email().as_ref().to_owned();
+    let link = confirmed_subscriber_link(&test_app, &email, "/subscriptions/preferences").await;
+
+    // Act
+    let response = reqwest::get(link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!("<p>Email: {email}</p>")));
+    assert!(html_page.contains("<p>Status: confirmed</p>"));
+    assert!(html_page.contains("/subscriptions/unsubscribe?subscription_token="));
+}
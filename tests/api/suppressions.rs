@@ -0,0 +1,287 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, UrlEncodable, assert_is_redirect_to, fake_name, spawn_app};
+
+async fn create_confirmed_subscriber(test_app: &TestApp, email: &str) {
+    let body = format!(
+        "name={}&email={}",
+        fake_name().url_encode(),
+        email.url_encode()
+    );
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_newsletter(test_app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = test_app
+        .post_publish_newsletter(&newsletter_request_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_suppression_list() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app.get_suppressions(None).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_add_to_the_suppression_list() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "someone@example.com",
+            "reason": "manual",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn added_entries_are_listed_with_their_reason() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act - Part 1 - Add an entry
+    let response = test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "Bounced@Example.com",
+            "reason": "bounce",
+            "note": "Mailbox does not exist",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = test_app.get_suppressions_html(None).await;
+    assert!(
+        html_page
+            .contains("<p><i>bounced@example.com has been added to the suppression list.</i></p>")
+    );
+    assert!(html_page.contains("<td>bounce</td>"));
+    assert!(html_page.contains("<td>Mailbox does not exist</td>"));
+
+    // Act - Part 3 - Look up why the address is suppressed
+    let html_page = test_app
+        .get_suppressions_html(Some("bounced@example.com"))
+        .await;
+    assert!(html_page.contains("(reason: bounce)"));
+}
+
+#[tokio::test]
+async fn invalid_entries_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"address": "not a domain", "reason": "manual"}),
+            "<p><i>not a domain is not a valid email address or domain.</i></p>",
+        ),
+        (
+            serde_json::json!({"address": "example.com", "reason": "spam"}),
+            "<p><i>spam is not a valid suppression reason.</i></p>",
+        ),
+    ];
+
+    for (body, error_message) in test_cases {
+        // Act
+        let response = test_app.post_add_suppression(&body).await;
+        assert_is_redirect_to(&response, "/admin/suppressions");
+
+        // Assert
+        let html_page = test_app.get_suppressions_html(None).await;
+        assert!(html_page.contains(error_message));
+    }
+}
+
+#[tokio::test]
+async fn removed_entries_no_longer_suppress_an_address() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "example.com",
+            "reason": "complaint",
+        }))
+        .await;
+    let suppression_id =
+        sqlx::query!("SELECT suppression_id FROM suppressions WHERE value = 'example.com'")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap()
+            .suppression_id;
+
+    // Act
+    let response = test_app
+        .post_remove_suppression(&serde_json::json!({
+            "suppression_id": suppression_id,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = test_app
+        .get_suppressions_html(Some("someone@example.com"))
+        .await;
+    assert!(html_page.contains("someone@example.com is not suppressed."));
+}
+
+#[tokio::test]
+async fn no_confirmation_email_is_sent_to_a_suppressed_address() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "ursula@example.com",
+            "reason": "manual",
+        }))
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_domains() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app, "ursula@blocked.example.com").await;
+    create_confirmed_subscriber(&test_app, "ursula@allowed.example.com").await;
+    test_app.test_user.login(&test_app).await;
+    test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "blocked.example.com",
+            "reason": "complaint",
+        }))
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&test_app).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_request.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "ursula@allowed.example.com");
+}
+
+#[tokio::test]
+async fn queued_emails_are_not_sent_to_addresses_suppressed_after_publishing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app, "ursula@example.com").await;
+    test_app.test_user.login(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    publish_newsletter(&test_app).await;
+    test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "ursula@example.com",
+            "reason": "unsubscribe",
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_queued = sqlx::query!("SELECT count(*) as \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn rejected_entries_are_escaped_in_the_error_message() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_add_suppression(&serde_json::json!({
+            "address": "<script>alert('boom')</script>",
+            "reason": "manual"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    // Assert
+    let html_page = test_app.get_suppressions_html(None).await;
+    assert!(!html_page.contains("<script>"));
+    assert!(html_page.contains("&lt;script&gt;alert('boom')&lt;/script&gt; is not a valid"));
+}
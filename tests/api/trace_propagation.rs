@@ -0,0 +1,98 @@
+use wiremock::{
+    Mock, ResponseTemplate,
+    matchers::{any, method, path},
+};
+
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// W3C `traceparent` format: version-trace_id-parent_id-flags.
+fn trace_id(traceparent: &str) -> &str {
+    traceparent.split('-').nth(1).unwrap()
+}
+
+#[tokio::test]
+async fn queued_deliveries_carry_the_trace_context_of_the_publishing_request() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+    test_app.test_user.login(&test_app).await;
+
+    // Act
+    let response = test_app
+        .api_client
+        .post(format!("{}/admin/newsletters", &test_app.address))
+        .header("traceparent", format!("00-{TRACE_ID}-00f067aa0ba902b7-01"))
+        .form(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let task = sqlx::query!("SELECT traceparent FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(trace_id(&task.traceparent.unwrap()), TRACE_ID);
+}
+
+#[tokio::test]
+async fn requests_to_the_email_api_carry_trace_headers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app
+        .api_client
+        .post(format!("{}/subscribe", &test_app.address))
+        .header("traceparent", format!("00-{TRACE_ID}-00f067aa0ba902b7-01"))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&form_token={}",
+            test_app.form_token()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    test_app.dispatch_all_confirmation_emails().await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let traceparent = email_request.headers["traceparent"].to_str().unwrap();
+    assert_eq!(trace_id(traceparent), TRACE_ID);
+}